@@ -1,15 +1,54 @@
 use LibState;
-use view::Layer;
+use view::{View, Layer};
+use entity::{Component, EntityID};
+use entity::core::{ComponentOpacity, ComponentTransform};
+use entity::animation::{AnimProperty, AnimSource, AnimEventKind, ComponentAnimPlayback};
+use view::component_list::ComponentList;
+use std::f64;
 
-/// Increments all animationn timers for the given layer, and repeats the
-/// process recursively for nested layers.
-fn increment_animation_timers(frame_delta: u64, layer: &mut Layer) {
+/// Returns the delta an entity's animations are moved on by, after applying
+/// its ComponentAnimPlayback (if it has one) to the layer's delta.
+fn get_entity_delta(playback: &ComponentList<ComponentAnimPlayback>, e_id: EntityID,
+                    frame_delta: f64) -> f64 {
+  playback.get_component(e_id).map_or(frame_delta, |p| frame_delta * p.get_rate())
+}
+
+/// Moves an animation timer on by delta, keeping it between 0 and len.
+fn step_timer(timer: f64, len: f64, delta: f64) -> f64 {
+  (timer + delta).max(0.0).min(len)
+}
+
+/// Returns true if an animation moving by delta per frame has finished, i.e
+/// reached its end, or its start when playing backwards.
+fn is_finished(timer: f64, len: f64, delta: f64) -> bool {
+  if delta < 0.0 { timer <= 0.0 } else { timer >= len }
+}
+
+/// Returns true if an entity is in a list of entities sorted by ID, such as
+/// the IDs of a ComponentList's components in order.
+fn is_listed(sorted: &[EntityID], e_id: EntityID) -> bool {
+  sorted.binary_search_by_key(&e_id.0, |e| e.0).is_ok()
+}
+
+/// Increments all animationn timers for the given layer by the frame delta in
+/// seconds, and repeats the process recursively for nested layers.
+fn increment_animation_timers(frame_delta: f64, layer: &mut Layer) {
+  let playback = &layer.component_anim_playback;
   for anim in &mut layer.component_anim_translate {
-    anim.anim_timer += (frame_delta / 1000000) as u32;
-    if anim.anim_timer > anim.anim_len {
-      anim.anim_timer = anim.anim_len;
+    let delta = get_entity_delta(playback, anim.entity_id, frame_delta);
+    anim.anim_timer = step_timer(anim.anim_timer, anim.get_len(), delta);
+  }
+  for anim in &mut layer.component_anim_property {
+    let delta = get_entity_delta(playback, anim.entity_id, frame_delta);
+    for a in &mut anim.anims {
+      a.anim_timer = step_timer(a.anim_timer, a.get_len(), delta);
     }
   }
+  for anim in &mut layer.component_anim_timeline {
+    let delta = get_entity_delta(playback, anim.entity_id, frame_delta);
+    let len = anim.animation.get_len().unwrap_or(f64::INFINITY);
+    anim.anim_timer = step_timer(anim.anim_timer, len, delta);
+  }
   // Recursively call function for nested layers
   for l in &mut layer.component_layer {
     increment_animation_timers(frame_delta, l);
@@ -17,28 +56,44 @@ fn increment_animation_timers(frame_delta: u64, layer: &mut Layer) {
 }
 
 /// Process translation animations on a given layer, and all of its nested layers.
-fn process_layer_translate_animations(frame_delta: u64, layer: &mut Layer) {
-  // List of dead animation indexes
-  let mut dead_anim = Vec::<usize>::new();
+fn process_layer_translate_animations(frame_delta: f64, layer: &mut Layer) {
+  // Entities whose animations have finished, sorted as they're found in order
+  let mut dead_anim = Vec::<EntityID>::new();
+  let mut moved = false;
   for ii in 0..layer.component_anim_translate.len() {
     let anim = &mut layer.component_anim_translate[ii];
-    let interp = anim.tween(); // Get interpolation value between 0 and 1
+    let (x, y) = anim.get_position_at(anim.anim_timer);
+    let delta = get_entity_delta(&layer.component_anim_playback, anim.entity_id,
+                                 frame_delta);
+    let finished = is_finished(anim.anim_timer, anim.get_len(), delta);
+    if finished {
+      dead_anim.push(anim.entity_id);
+    }
+    moved |= finished || delta != 0.0;
 
     let aabb = layer.component_aabb.get_component_mut(anim.entity_id);
     if aabb.is_none() { continue; }
     let aabb = aabb.unwrap();
-    aabb.x = anim.start_x + (anim.end_x - anim.start_x) * interp;
-    aabb.y = anim.start_y + (anim.end_y - anim.start_y) * interp;
+    aabb.x = x;
+    aabb.y = y;
 
-    if anim.anim_timer >= anim.anim_len {
-      aabb.x = anim.end_x;
-      aabb.y = anim.end_y;
-      dead_anim.push(ii);
+    if finished {
+      if delta < 0.0 {
+        aabb.x = anim.start_x;
+        aabb.y = anim.start_y;
+      }
+      else {
+        aabb.x = anim.end_x;
+        aabb.y = anim.end_y;
+      }
     }
   }
-  for dead in dead_anim {
-    
-    layer.component_anim_translate.remove(dead);
+  if moved {
+    layer.mark_dirty();
+  }
+  layer.component_anim_translate.retain(|a| !is_listed(&dead_anim, a.entity_id));
+  for e_id in dead_anim {
+    layer.push_anim_event(e_id, AnimSource::Translate, AnimEventKind::Completed);
   }
 
   // Recursively call function for nested layers
@@ -47,15 +102,508 @@ fn process_layer_translate_animations(frame_delta: u64, layer: &mut Layer) {
   }
 }
 
+/// Linearly interpolates from a to b.
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+  a + (b - a) * t
+}
+
+/// Returns an entity's ComponentTransform, adding one if it doesn't have one.
+fn get_transform_mut(layer: &mut Layer, e_id: EntityID) -> &mut ComponentTransform {
+  if layer.component_transform.get_component(e_id).is_none() {
+    layer.component_transform.add_component(ComponentTransform::new(e_id));
+  }
+  layer.component_transform.get_component_mut(e_id).unwrap()
+}
+
+/// Sets an entity's property to the value t of the way from its animation's
+/// start value to its end value.
+fn apply_property(layer: &mut Layer, e_id: EntityID, property: &AnimProperty, t: f32) {
+  match *property {
+    AnimProperty::Position {start, end} => {
+      let aabb = layer.component_aabb.get_component_mut(e_id);
+      if aabb.is_none() { return; }
+      let aabb = aabb.unwrap();
+      aabb.x = lerp(start.0, end.0, t);
+      aabb.y = lerp(start.1, end.1, t);
+    },
+    AnimProperty::Width {start, end} => {
+      let aabb = layer.component_aabb.get_component_mut(e_id);
+      if aabb.is_none() { return; }
+      aabb.unwrap().w = lerp(start, end, t).max(0.0);
+    },
+    AnimProperty::Height {start, end} => {
+      let aabb = layer.component_aabb.get_component_mut(e_id);
+      if aabb.is_none() { return; }
+      aabb.unwrap().h = lerp(start, end, t).max(0.0);
+    },
+    AnimProperty::BackgroundColor {ref start, ref end} => {
+      let background = layer.component_background.get_component_mut(e_id);
+      if background.is_none() { return; }
+      background.unwrap().color = start.lerp(end, t);
+    },
+    AnimProperty::Opacity {start, end} => {
+      let value = lerp(start, end, t).max(0.0).min(1.0);
+      if layer.component_opacity.get_component(e_id).is_none() {
+        layer.component_opacity.add_component(ComponentOpacity {
+          entity_id: e_id,
+          opacity: value,
+          hide_from_input: false,
+        });
+      }
+      else {
+        layer.component_opacity.get_component_mut(e_id).unwrap().opacity = value;
+      }
+    },
+    AnimProperty::Rotation {start, end} => {
+      get_transform_mut(layer, e_id).rotation = lerp(start, end, t);
+    },
+    AnimProperty::Scale {start, end} => {
+      get_transform_mut(layer, e_id).scale = (lerp(start.0, end.0, t),
+                                              lerp(start.1, end.1, t));
+    },
+  }
+}
+
+/// Process property animations on a given layer, and all of its nested
+/// layers. Finished animations are removed, along with components with no
+/// animations left.
+fn process_layer_property_animations(frame_delta: f64, layer: &mut Layer) {
+  let mut moved = false;
+  for ii in 0..layer.component_anim_property.len() {
+    let e_id;
+    let values : Vec<(AnimProperty, f32)>;
+    let completed : Vec<AnimProperty>;
+    {
+      let anim = &mut layer.component_anim_property[ii];
+      e_id = anim.entity_id;
+      let delta = get_entity_delta(&layer.component_anim_playback, e_id, frame_delta);
+      values = anim.anims.iter().map(|a| (a.property.clone(), a.tween())).collect();
+      completed = anim.anims.iter().filter(|a| is_finished(a.anim_timer, a.get_len(), delta))
+        .map(|a| a.property.clone()).collect();
+      anim.anims.retain(|a| !is_finished(a.anim_timer, a.get_len(), delta));
+      moved |= completed.len() > 0 || delta != 0.0;
+    }
+    for (property, t) in values {
+      apply_property(layer, e_id, &property, t);
+    }
+    for property in completed {
+      layer.push_anim_event(e_id, AnimSource::Property(property),
+                            AnimEventKind::Completed);
+    }
+  }
+  if moved {
+    layer.mark_dirty();
+  }
+  layer.component_anim_property.retain(|a| a.anims.len() > 0);
+
+  for l in &mut layer.component_layer {
+    process_layer_property_animations(frame_delta, l);
+  }
+}
+
+/// Process timeline animations on a given layer, and all of its nested layers.
+/// Finished timelines are removed.
+fn process_layer_timeline_animations(frame_delta: f64, layer: &mut Layer) {
+  let mut dead_anim = Vec::<EntityID>::new();
+  let mut moved = false;
+  let mut values = Vec::new();
+  for ii in 0..layer.component_anim_timeline.len() {
+    let anim = &layer.component_anim_timeline[ii];
+    anim.animation.sample(anim.anim_timer, &mut values);
+    let delta = get_entity_delta(&layer.component_anim_playback, anim.entity_id,
+                                 frame_delta);
+    let len = anim.animation.get_len().unwrap_or(f64::INFINITY);
+    let finished = is_finished(anim.anim_timer, len, delta);
+    if finished {
+      dead_anim.push(anim.entity_id);
+    }
+    moved |= finished || delta != 0.0;
+  }
+  for (e_id, property, t) in values {
+    apply_property(layer, e_id, &property, t);
+  }
+  if moved {
+    layer.mark_dirty();
+  }
+  layer.component_anim_timeline.retain(|a| !is_listed(&dead_anim, a.entity_id));
+  for e_id in dead_anim {
+    layer.push_anim_event(e_id, AnimSource::Timeline, AnimEventKind::Completed);
+  }
+
+  for l in &mut layer.component_layer {
+    process_layer_timeline_animations(frame_delta, l);
+  }
+}
+
+/// Moves one axis of an AABB by the given amount, keeping it inside the
+/// bounds from min to max if it fits. Returns the velocity on the axis, which
+/// is reversed and scaled by bounce if the bounds were hit.
+fn move_within(pos: &mut f32, size: f32, moved: f32, velocity: f32,
+               min: f32, max: f32, bounce: f32) -> f32 {
+  *pos += moved;
+  if max - min < size { return velocity; }
+  if *pos <= min && velocity < 0.0 {
+    *pos = min;
+    return -velocity * bounce;
+  }
+  else if *pos + size >= max && velocity > 0.0 {
+    *pos = max - size;
+    return -velocity * bounce;
+  }
+  return velocity;
+}
+
+/// Process decay animations on a given layer, and all of its nested layers.
+/// Animations are removed once their entity comes to rest, and entities with
+/// a ComponentScrollSnap then snap.
+fn process_layer_decay_animations(frame_delta: f64, layer: &mut Layer) {
+  let mut dead_anim = Vec::<EntityID>::new();
+  let mut moved = false;
+  for ii in 0..layer.component_anim_decay.len() {
+    let anim = &mut layer.component_anim_decay[ii];
+    let delta = get_entity_delta(&layer.component_anim_playback, anim.entity_id,
+                                 frame_delta);
+    let aabb = layer.component_aabb.get_component_mut(anim.entity_id);
+    if aabb.is_none() {
+      dead_anim.push(anim.entity_id);
+      continue;
+    }
+    let aabb = aabb.unwrap();
+    let step = anim.step(delta);
+    moved |= step != (0.0, 0.0);
+    if anim.bounds.is_some() {
+      let b = anim.bounds.unwrap();
+      anim.velocity.0 = move_within(&mut aabb.x, aabb.w, step.0, anim.velocity.0,
+                                    b.x, b.x + b.w, anim.bounce);
+      anim.velocity.1 = move_within(&mut aabb.y, aabb.h, step.1, anim.velocity.1,
+                                    b.y, b.y + b.h, anim.bounce);
+    }
+    else {
+      aabb.x += step.0;
+      aabb.y += step.1;
+    }
+    if anim.is_at_rest() {
+      dead_anim.push(anim.entity_id);
+    }
+  }
+  if moved {
+    layer.mark_dirty();
+  }
+  layer.component_anim_decay.retain(|a| !is_listed(&dead_anim, a.entity_id));
+  for e_id in dead_anim {
+    layer.push_anim_event(e_id, AnimSource::Decay, AnimEventKind::Completed);
+    // Hand flung entities over to their scroll snapping once at rest
+    layer.start_scroll_snap(e_id);
+  }
+
+  for l in &mut layer.component_layer {
+    process_layer_decay_animations(frame_delta, l);
+  }
+}
+
 /// Process all animations
 pub fn process_animations(lib_state: &mut LibState) {
+  // Frame delta in seconds, scaled by the global time scale
+  let mut frame_delta = lib_state.frame_delta as f64 / 1000000000.0 *
+    lib_state.get_time_scale();
+  if lib_state.is_animation_paused() { frame_delta = 0.0; }
+
   // Get top view
   let view = lib_state.view_stack.last_mut();
   if view.is_none() { return; }
-  let view = view.unwrap();
-  
+  process_view_animations(view.unwrap(), frame_delta);
+}
+
+/// Moves a view's animations on by frame_delta seconds, scaled by the view's
+/// animation rate, and applies them to its layers.
+pub fn process_view_animations(view: &mut View, frame_delta: f64) {
+  let mut frame_delta = frame_delta * view.get_animation_rate();
+  if view.is_animation_paused() { frame_delta = 0.0; }
   for layer in &mut view.layers {
-    increment_animation_timers(lib_state.frame_delta, layer);
-    process_layer_translate_animations(lib_state.frame_delta, layer);
+    increment_animation_timers(frame_delta, layer);
+    process_layer_translate_animations(frame_delta, layer);
+    process_layer_property_animations(frame_delta, layer);
+    process_layer_timeline_animations(frame_delta, layer);
+    process_layer_decay_animations(frame_delta, layer);
+  }
+}
+
+/// Returns true if any of the components in the list belong to an entity
+/// whose animations are playing, i.e not paused or at a rate of 0.
+fn has_playing<T: Component>(list: &ComponentList<T>,
+                             playback: &ComponentList<ComponentAnimPlayback>) -> bool {
+  list.into_iter().any(|a| {
+    playback.get_component(a.get_entity_id()).map_or(true, |p| p.get_rate() != 0.0)
+  })
+}
+
+/// Returns true if any layer in the view, or any nested layer, has an
+/// animation running. Animations aren't running while the view's animations
+/// are paused or at a rate of 0, or while their entity's are.
+pub fn is_animating(view: &View) -> bool {
+  if view.is_animation_paused() || view.get_animation_rate() == 0.0 { return false; }
+  fn layer_is_animating(layer: &Layer) -> bool {
+    let playback = &layer.component_anim_playback;
+    if has_playing(&layer.component_anim_translate, playback) ||
+      has_playing(&layer.component_anim_property, playback) ||
+      has_playing(&layer.component_anim_timeline, playback) ||
+      has_playing(&layer.component_anim_decay, playback) {
+      return true;
+    }
+    for l in &layer.component_layer {
+      if layer_is_animating(l) { return true; }
+    }
+    return false;
+  }
+  view.layers.iter().any(layer_is_animating)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use entity::core::ComponentAABB;
+  use entity::animation::{ComponentAnimTranslate, ComponentAnimTimeline, Animation,
+                          AnimEvent, TweenFunction};
+
+  fn add_entity(layer: &mut Layer, e_id: EntityID, x: f32, y: f32) {
+    layer.component_aabb.add_component(ComponentAABB {
+      entity_id: e_id, x: x, y: y, w: 10.0, h: 10.0,
+    });
+  }
+
+  fn translate(e_id: EntityID, end_x: f32, end_y: f32, anim_len: f64,
+               tween_func: TweenFunction) -> ComponentAnimTranslate {
+    ComponentAnimTranslate {
+      entity_id: e_id,
+      start_x: 0.0, start_y: 0.0,
+      end_x: end_x, end_y: end_y,
+      anim_len: anim_len,
+      anim_timer: 0.0,
+      tween_func: tween_func,
+      start_velocity: None,
+    }
+  }
+
+  fn get_pos(layer: &Layer, e_id: EntityID) -> (f32, f32) {
+    let aabb = layer.component_aabb.get_component(e_id).unwrap();
+    (aabb.x, aabb.y)
+  }
+
+  #[test]
+  fn step_animations_moves_tween() {
+    let e_id = EntityID(1);
+    let mut view = View::new();
+    let mut layer = Layer::new();
+    add_entity(&mut layer, e_id, 0.0, 0.0);
+    layer.component_anim_translate.add_component(
+      translate(e_id, 100.0, 50.0, 1.0, TweenFunction::Linear));
+    view.layers.push(layer);
+
+    for _ in 0..4 {
+      view.step_animations(0.125);
+    }
+    assert_eq!(get_pos(&view.layers[0], e_id), (50.0, 25.0));
+
+    view.step_animations(0.5);
+    assert_eq!(get_pos(&view.layers[0], e_id), (100.0, 50.0));
+    assert_eq!(view.layers[0].component_anim_translate.len(), 0);
+  }
+
+  /// Runs every animation pass over a layer, as process_view_animations does.
+  fn step(layer: &mut Layer, delta: f64) {
+    increment_animation_timers(delta, layer);
+    process_layer_translate_animations(delta, layer);
+    process_layer_property_animations(delta, layer);
+    process_layer_timeline_animations(delta, layer);
+    process_layer_decay_animations(delta, layer);
+  }
+
+  /// Fills a layer with three entities from first_id. The first two have
+  /// animations finishing after 0.5s: a translation each, then a fade on the
+  /// first and a timeline on the second. The third's run for 2s.
+  fn add_animations(layer: &mut Layer, first_id: u16) {
+    let ids = [EntityID(first_id), EntityID(first_id + 1), EntityID(first_id + 2)];
+    for (ii, e_id) in ids.iter().enumerate() {
+      let end = first_id as f32 + ii as f32 * 10.0;
+      let anim_len = if ii < 2 { 0.5 } else { 2.0 };
+      add_entity(layer, *e_id, 0.0, 0.0);
+      layer.add_anim_translate(translate(*e_id, end, -end, anim_len,
+                                         TweenFunction::EaseInOut));
+    }
+    layer.add_anim_opacity(ids[0], 1.0, 0.0, 0.5, TweenFunction::Linear);
+    layer.add_anim_opacity(ids[2], 1.0, 0.0, 2.0, TweenFunction::Linear);
+    layer.add_anim_timeline(ComponentAnimTimeline::new(ids[1], Animation::Tween {
+      entity_id: ids[1],
+      property: AnimProperty::Width { start: 10.0, end: 20.0 },
+      anim_len: 0.5,
+      tween_func: TweenFunction::Linear,
+    }));
+  }
+
+  /// Returns the (entity, source) of each event of the given kind, sorted.
+  fn sources(events: &[AnimEvent], kind: AnimEventKind) -> Vec<(u16, &'static str)> {
+    let mut out : Vec<(u16, &'static str)> = events.iter()
+      .filter(|e| e.kind == kind)
+      .map(|e| (e.entity_id.0, match e.source {
+        AnimSource::Translate => "translate",
+        AnimSource::Property(AnimProperty::Opacity {..}) => "opacity",
+        AnimSource::Property(_) => "property",
+        AnimSource::Timeline => "timeline",
+        AnimSource::Decay => "decay",
+      })).collect();
+    out.sort();
+    out
+  }
+
+  fn check_layer(layer: &Layer, events: &[AnimEvent], first_id: u16) {
+    let ids : Vec<u16> = layer.component_anim_translate.into_iter()
+      .map(|a| a.entity_id.0).collect();
+    assert_eq!(ids, vec![first_id + 2]);
+    let ids : Vec<u16> = layer.component_anim_property.into_iter()
+      .map(|a| a.entity_id.0).collect();
+    assert_eq!(ids, vec![first_id + 2]);
+    assert_eq!(layer.component_anim_timeline.len(), 0);
+
+    for ii in 0..2 {
+      let end = first_id as f32 + ii as f32 * 10.0;
+      assert_eq!(get_pos(layer, EntityID(first_id + ii)), (end, -end));
+    }
+    let opacity = layer.component_opacity.get_component(EntityID(first_id));
+    assert_eq!(opacity.unwrap().opacity, 0.0);
+    let aabb = layer.component_aabb.get_component(EntityID(first_id + 1));
+    assert_eq!(aabb.unwrap().w, 20.0);
+
+    let expected = vec![(first_id, "opacity"), (first_id, "translate"),
+                        (first_id + 1, "timeline"), (first_id + 1, "translate")];
+    assert_eq!(sources(events, AnimEventKind::Completed), expected);
+  }
+
+  #[test]
+  fn finished_animations_are_retired() {
+    let nested_id = EntityID(100);
+    let mut root = Layer::new();
+    add_animations(&mut root, 1);
+    add_entity(&mut root, nested_id, 0.0, 0.0);
+    let mut nested = Layer::new();
+    nested.entity_id = Some(nested_id);
+    add_animations(&mut nested, 20);
+    root.component_layer.add_component(nested);
+
+    // Every animation finishing after 0.5s does so on the second frame
+    for _ in 0..3 {
+      step(&mut root, 0.25);
+    }
+    let mut nested_events = Vec::new();
+    root.component_layer[0].drain_anim_events(&mut nested_events);
+    let mut root_events = Vec::new();
+    root.drain_anim_events(&mut root_events);
+    check_layer(&root, &root_events, 1);
+    check_layer(&root.component_layer[0], &nested_events, 20);
+  }
+
+  #[test]
+  fn retarget_keeps_velocity() {
+    let e_id = EntityID(1);
+    let mut layer = Layer::new();
+    add_entity(&mut layer, e_id, 0.0, 0.0);
+    layer.add_anim_translate(translate(e_id, 100.0, 0.0, 1.0, TweenFunction::EaseInOut));
+    layer.set_animation_rate(e_id, 2.0);
+    step(&mut layer, 0.25);
+    let before = {
+      let anim = layer.component_anim_translate.get_component(e_id).unwrap();
+      let v = anim.get_velocity(2.0);
+      assert!((v.0 - 2.0 * anim.get_velocity(1.0).0).abs() < 1e-2);
+      v
+    };
+
+    // Ease out starts fast towards its end, the blend has to cancel that out
+    layer.retarget_translate(e_id, 0.0, 50.0, 1.0, TweenFunction::EaseOut);
+    let anim = layer.component_anim_translate.get_component(e_id).unwrap();
+    let after = anim.get_velocity(2.0);
+    assert!((after.0 - before.0).abs() < before.0.abs() * 0.01);
+    assert!(after.1.abs() < before.0.abs() * 0.01);
+  }
+
+  #[test]
+  fn replacing_animation_cancels_it_once() {
+    let e_id = EntityID(1);
+    let mut layer = Layer::new();
+    add_entity(&mut layer, e_id, 0.0, 0.0);
+    layer.add_anim_translate(translate(e_id, 100.0, 0.0, 1.0, TweenFunction::Linear));
+    layer.add_anim_timeline(ComponentAnimTimeline::new(e_id, Animation::Tween {
+      entity_id: e_id,
+      property: AnimProperty::Width { start: 10.0, end: 20.0 },
+      anim_len: 1.0,
+      tween_func: TweenFunction::Linear,
+    }));
+    step(&mut layer, 0.5);
+
+    layer.add_anim_translate(translate(e_id, -100.0, 0.0, 0.25, TweenFunction::Linear));
+    layer.add_anim_timeline(ComponentAnimTimeline::new(e_id, Animation::Tween {
+      entity_id: e_id,
+      property: AnimProperty::Width { start: 10.0, end: 30.0 },
+      anim_len: 0.25,
+      tween_func: TweenFunction::Linear,
+    }));
+    assert_eq!(layer.component_anim_translate.len(), 1);
+    assert_eq!(layer.component_anim_timeline.len(), 1);
+    for _ in 0..8 {
+      step(&mut layer, 0.125);
+    }
+    assert_eq!(get_pos(&layer, e_id), (-100.0, 0.0));
+    assert_eq!(layer.component_aabb.get_component(e_id).unwrap().w, 30.0);
+
+    let mut events = Vec::new();
+    layer.drain_anim_events(&mut events);
+    let expected = vec![(1, "timeline"), (1, "translate")];
+    assert_eq!(sources(&events, AnimEventKind::Cancelled), expected);
+    assert_eq!(sources(&events, AnimEventKind::Completed), expected);
+  }
+
+  #[test]
+  fn retarget_replaces_translation() {
+    let e_id = EntityID(1);
+    let mut layer = Layer::new();
+    add_entity(&mut layer, e_id, 0.0, 0.0);
+    layer.add_anim_translate(translate(e_id, 100.0, 0.0, 1.0, TweenFunction::Linear));
+    step(&mut layer, 0.5);
+    layer.retarget_translate(e_id, 200.0, 0.0, 1.0, TweenFunction::Linear);
+    assert_eq!(layer.component_anim_translate.len(), 1);
+    for _ in 0..4 {
+      step(&mut layer, 0.5);
+    }
+    assert_eq!(get_pos(&layer, e_id), (200.0, 0.0));
+    assert_eq!(layer.component_anim_translate.len(), 0);
+  }
+
+  #[test]
+  fn paused_animations_let_the_view_idle() {
+    let e_id = EntityID(1);
+    let mut layer = Layer::new();
+    add_entity(&mut layer, e_id, 0.0, 0.0);
+    layer.add_anim_translate(translate(e_id, 100.0, 0.0, 1.0, TweenFunction::Linear));
+    layer.add_anim_opacity(e_id, 1.0, 0.0, 1.0, TweenFunction::Linear);
+    let mut view = View::new();
+    view.layers.push(layer);
+    view.step_animations(0.25);
+    assert!(is_animating(&view));
+
+    view.layers[0].pause_animations(e_id);
+    assert!(!is_animating(&view));
+    view.layers[0].clear_dirty();
+    view.step_animations(0.25);
+    assert!(!view.layers[0].is_dirty());
+    assert_eq!(get_pos(&view.layers[0], e_id), (25.0, 0.0));
+
+    view.layers[0].resume_animations(e_id);
+    view.set_animation_rate(0.0);
+    assert!(!is_animating(&view));
+    view.step_animations(0.25);
+    assert!(!view.layers[0].is_dirty());
+
+    view.set_animation_rate(1.0);
+    view.step_animations(0.25);
+    assert!(view.layers[0].is_dirty());
+    assert_eq!(get_pos(&view.layers[0], e_id), (50.0, 0.0));
   }
 }
@@ -22,3 +22,37 @@ pub struct RGBAf32 {
   pub b: f32,
   pub a: f32,
 }
+
+impl RGBAf32 {
+  pub fn new(r: f32, g: f32, b: f32, a: f32) -> RGBAf32 {
+    RGBAf32 {
+      r: r,
+      g: g,
+      b: b,
+      a: a,
+    }
+  }
+
+  /// Returns the colour as an [r, g, b, a] array, as used by vertex data.
+  pub fn to_array(&self) -> [f32; 4] {
+    [self.r, self.g, self.b, self.a]
+  }
+
+  /// Linearly interpolates between this colour and another. t = 0 returns
+  /// this colour, t = 1 returns other.
+  pub fn lerp(&self, other: &RGBAf32, t: f32) -> RGBAf32 {
+    RGBAf32 {
+      r: self.r + (other.r - self.r) * t,
+      g: self.g + (other.g - self.g) * t,
+      b: self.b + (other.b - self.b) * t,
+      a: self.a + (other.a - self.a) * t,
+    }
+  }
+}
+
+impl From<RGBf32> for RGBAf32 {
+  /// Converts an RGB colour to a fully opaque RGBA colour.
+  fn from(c: RGBf32) -> RGBAf32 {
+    RGBAf32::new(c.r, c.g, c.b, 1.0)
+  }
+}
@@ -3,3 +3,12 @@ pub mod color;
 
 /// Contains vector operations
 pub mod vec;
+
+/// Contains a rectangle struct used for clipping and hit testing
+pub mod rect;
+
+/// Contains density independent unit (dp) conversion
+pub mod units;
+
+/// Contains a 2D affine transform struct
+pub mod transform;
@@ -0,0 +1,82 @@
+/// An axis aligned rectangle in view coordinates, where (0, 0) is the top left
+/// of the view and y increases downwards.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+  pub x: f32,
+  pub y: f32,
+  pub w: f32,
+  pub h: f32,
+}
+
+impl Rect {
+  pub fn new(x: f32, y: f32, w: f32, h: f32) -> Rect {
+    Rect {
+      x: x,
+      y: y,
+      w: w,
+      h: h,
+    }
+  }
+
+  /// Returns the overlapping area of this rect and another. If the rects do
+  /// not overlap, the returned rect has a width or height of 0.
+  pub fn intersect(&self, other: &Rect) -> Rect {
+    let x0 = self.x.max(other.x);
+    let y0 = self.y.max(other.y);
+    let x1 = (self.x + self.w).min(other.x + other.w);
+    let y1 = (self.y + self.h).min(other.y + other.h);
+    Rect::new(x0, y0, (x1 - x0).max(0.0), (y1 - y0).max(0.0))
+  }
+
+  /// Returns the smallest rect containing both this rect and another.
+  pub fn union(&self, other: &Rect) -> Rect {
+    let x0 = self.x.min(other.x);
+    let y0 = self.y.min(other.y);
+    let x1 = (self.x + self.w).max(other.x + other.w);
+    let y1 = (self.y + self.h).max(other.y + other.h);
+    Rect::new(x0, y0, x1 - x0, y1 - y0)
+  }
+
+  /// Returns true if the given point is inside this rect.
+  pub fn contains(&self, x: f32, y: f32) -> bool {
+    x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+  }
+
+  /// Returns true if this rect has no area.
+  pub fn is_empty(&self) -> bool {
+    self.w <= 0.0 || self.h <= 0.0
+  }
+}
+
+/// Distances inwards from each edge of a rect, e.g padding.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Insets {
+  pub left: f32,
+  pub top: f32,
+  pub right: f32,
+  pub bottom: f32,
+}
+
+impl Insets {
+  pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Insets {
+    Insets {
+      left: left,
+      top: top,
+      right: right,
+      bottom: bottom,
+    }
+  }
+
+  /// Returns these insets scaled by s, e.g to convert between units.
+  pub fn scale(&self, s: f32) -> Insets {
+    Insets::new(self.left * s, self.top * s, self.right * s, self.bottom * s)
+  }
+
+  /// Returns the rect inside these insets. The width and height never go
+  /// below 0.
+  pub fn inset(&self, rect: &Rect) -> Rect {
+    Rect::new(rect.x + self.left, rect.y + self.top,
+              (rect.w - self.left - self.right).max(0.0),
+              (rect.h - self.top - self.bottom).max(0.0))
+  }
+}
@@ -0,0 +1,94 @@
+use common::rect::Rect;
+
+/// A 2D affine transform, mapping (x, y) to
+/// (a * x + c * y + e, b * x + d * y + f).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Affine2 {
+  pub a: f32,
+  pub b: f32,
+  pub c: f32,
+  pub d: f32,
+  pub e: f32,
+  pub f: f32,
+}
+
+impl Affine2 {
+  pub fn identity() -> Affine2 {
+    Affine2 { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0 }
+  }
+
+  pub fn translate(x: f32, y: f32) -> Affine2 {
+    Affine2 { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: x, f: y }
+  }
+
+  pub fn scale(sx: f32, sy: f32) -> Affine2 {
+    Affine2 { a: sx, b: 0.0, c: 0.0, d: sy, e: 0.0, f: 0.0 }
+  }
+
+  /// Rotation by the given angle in radians. As y points down, positive
+  /// angles rotate clockwise on screen.
+  pub fn rotate(angle: f32) -> Affine2 {
+    let (sin, cos) = angle.sin_cos();
+    Affine2 { a: cos, b: sin, c: -sin, d: cos, e: 0.0, f: 0.0 }
+  }
+
+  /// Returns a transform which applies this transform, then the next.
+  pub fn then(&self, next: &Affine2) -> Affine2 {
+    Affine2 {
+      a: next.a * self.a + next.c * self.b,
+      b: next.b * self.a + next.d * self.b,
+      c: next.a * self.c + next.c * self.d,
+      d: next.b * self.c + next.d * self.d,
+      e: next.a * self.e + next.c * self.f + next.e,
+      f: next.b * self.e + next.d * self.f + next.f,
+    }
+  }
+
+  /// Applies this transform to a point.
+  pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+    (self.a * x + self.c * y + self.e, self.b * x + self.d * y + self.f)
+  }
+
+  /// Returns the inverse of this transform, or None if it can't be inverted
+  /// (e.g it scales to 0).
+  pub fn inverse(&self) -> Option<Affine2> {
+    let det = self.a * self.d - self.b * self.c;
+    if det == 0.0 { return None; }
+    Some(Affine2 {
+      a: self.d / det,
+      b: -self.b / det,
+      c: -self.c / det,
+      d: self.a / det,
+      e: (self.c * self.f - self.d * self.e) / det,
+      f: (self.b * self.e - self.a * self.f) / det,
+    })
+  }
+
+  /// Returns how much this transform scales areas by, as a length. Used to
+  /// keep anti-aliased edges one physical pixel wide when scaled.
+  pub fn get_scale_factor(&self) -> f32 {
+    (self.a * self.d - self.b * self.c).abs().sqrt()
+  }
+
+  pub fn is_identity(&self) -> bool {
+    *self == Affine2::identity()
+  }
+
+  /// Returns the bounding box of a rect once transformed.
+  pub fn transform_rect(&self, rect: &Rect) -> Rect {
+    if self.is_identity() { return *rect; }
+    let corners = [self.apply(rect.x, rect.y),
+                   self.apply(rect.x + rect.w, rect.y),
+                   self.apply(rect.x + rect.w, rect.y + rect.h),
+                   self.apply(rect.x, rect.y + rect.h)];
+    let (mut x0, mut y0) = corners[0];
+    let (mut x1, mut y1) = corners[0];
+    for &(x, y) in &corners[1..] {
+      x0 = x0.min(x);
+      y0 = y0.min(y);
+      x1 = x1.max(x);
+      y1 = y1.max(y);
+    }
+    Rect::new(x0, y0, x1 - x0, y1 - y0)
+  }
+}
@@ -0,0 +1,27 @@
+/// Android's baseline density (mdpi), at which 1dp is 1 physical pixel.
+#[cfg(target_os = "android")]
+const BASELINE_DENSITY: f32 = 160.0;
+
+/// Reads the device's screen density from its configuration, and returns the
+/// number of physical pixels per dp. Returns 1.0 if the density is unknown.
+#[cfg(target_os = "android")]
+pub fn read_device_scale() -> f32 {
+  use ffi_glue;
+  use ffi_glue::ffi;
+  let app = ffi_glue::get_app();
+  if app.config.is_null() { return 1.0; }
+  let density = unsafe {
+    ffi::AConfiguration_getDensity(app.config as *mut ffi::AConfiguration)
+  };
+  // ACONFIGURATION_DENSITY_DEFAULT (0) and ACONFIGURATION_DENSITY_NONE
+  // (0xffff) don't tell us anything useful.
+  if density <= 0 || density == 0xffff { return 1.0; }
+  density as f32 / BASELINE_DENSITY
+}
+
+/// There's no device configuration on desktop, so 1dp is 1 pixel. Use
+/// LibState::set_dpi_scale to override this.
+#[cfg(not(target_os = "android"))]
+pub fn read_device_scale() -> f32 {
+  1.0
+}
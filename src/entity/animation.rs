@@ -1,4 +1,8 @@
 use entity::{EntityID, Component};
+use common::color::RGBAf32;
+use common::rect::Rect;
+use std::mem;
+use LibState;
 
 /// Enum for different tween functions
 #[derive(Clone, Copy)]
@@ -13,30 +17,210 @@ pub enum TweenFunction {
   EaseOut,
   /// Ease in and out (start slow, end slow)
   EaseInOut,
+  /// CSS style cubic bezier curve (x1, y1, x2, y2), from (0, 0) to (1, 1).
+  /// The x values are clamped between 0 and 1. The y values can go outside
+  /// of 0 and 1 to overshoot.
+  CubicBezier(f32, f32, f32, f32),
+  /// Jumps between the start and end in the given number of equal steps,
+  /// jumping at the end of each step.
+  Steps(u32),
+  /// Ease out, bouncing off the end like a dropped ball.
+  Bounce,
+  /// Ease out, overshooting and springing back and forth around the end.
+  Elastic,
+  /// Ease out, overshooting the end slightly before settling back.
+  Back,
+  /// A spring pulling the value from the start to the end, starting at rest.
+  /// The animation's length is ignored, it lasts until the spring settles.
+  /// See TweenFunction::get_duration.
+  Spring {
+    /// Strength of the spring. Stiffer springs are faster.
+    stiffness: f32,
+    /// Friction slowing the spring down. With less damping the spring
+    /// oscillates around the end for longer.
+    damping: f32,
+    /// Mass on the end of the spring. Heavier springs are slower.
+    mass: f32,
+  },
+  /// Custom easing function, mapping progress from 0 to 1 to a tweened value
+  /// which should be 0 at the start and 1 at the end.
+  Custom(fn(f32) -> f32),
 }
 
+/// Distance from the end at which a spring is considered settled, relative to
+/// the distance between the start and end.
+const SPRING_SETTLE_TOLERANCE: f64 = 0.001;
+
+/// Longest a spring animation can run for in seconds, so undamped springs end.
+const SPRING_MAX_DURATION: f64 = 10.0;
+
 impl TweenFunction {
-  /// Given the animation length and timers, returns a transformed timer value
-  /// to reflect an instant translation.
-  fn tween(&self, anim_len: u32, anim_timer: u32) -> u32 { 
+  /// Given how far through an animation we are (from 0 to 1), returns how far
+  /// the animated value should have moved from its start to its end.
+  pub fn tween(&self, x: f32) -> f32 {
+    let x = x.max(0.0).min(1.0);
     match *self {
-      TweenFunction::Instant => anim_len,
-      TweenFunction::Linear => anim_timer,
-      TweenFunction::EaseIn => {
-        let x = anim_timer as f32 / anim_len as f32;
-        (anim_len as f32 * x.powi(2)) as u32
-      }
-      TweenFunction::EaseOut => {
-        let x = anim_timer as f32 / anim_len as f32;
-        (anim_len as f32 * (-(x-1.0).powi(2) + 1.0)) as u32
+      TweenFunction::Instant => 1.0,
+      TweenFunction::Linear => x,
+      TweenFunction::EaseIn => x.powi(2),
+      TweenFunction::EaseOut => -(x-1.0).powi(2) + 1.0,
+      // Smootherstep
+      TweenFunction::EaseInOut => 6.0*x.powi(5) - 15.0*x.powi(4) + 10.0*x.powi(3),
+      TweenFunction::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, x),
+      TweenFunction::Steps(n) => {
+        if n == 0 || x >= 1.0 { return 1.0; }
+        (x * n as f32).floor() / n as f32
+      },
+      TweenFunction::Bounce => bounce(x),
+      TweenFunction::Elastic => {
+        if x <= 0.0 || x >= 1.0 { return x; }
+        let c = 2.0 * ::std::f32::consts::PI / 3.0;
+        2.0f32.powf(-10.0 * x) * ((10.0 * x - 0.75) * c).sin() + 1.0
+      },
+      TweenFunction::Back => {
+        let c1 = 1.70158;
+        let c3 = c1 + 1.0;
+        1.0 + c3 * (x - 1.0).powi(3) + c1 * (x - 1.0).powi(2)
+      },
+      TweenFunction::Spring {stiffness, damping, mass} => {
+        if x >= 1.0 { return 1.0; }
+        let duration = spring_duration(stiffness, damping, mass);
+        spring_position(stiffness, damping, mass, x as f64 * duration) as f32
       },
-      TweenFunction::EaseInOut => {
-        let x = anim_timer as f32 / anim_len as f32;
-        // Smootherstep
-        (anim_len as f32 * (6.0*x.powi(5) - 15.0*x.powi(4) + 10.0*x.powi(3))) as u32
+      TweenFunction::Custom(f) => {
+        if x >= 1.0 { return 1.0; }
+        f(x)
       },
     }
   }
+
+  /// Returns the length in seconds the tween function needs, if it decides
+  /// the length itself rather than using the animation's length. Springs
+  /// last until they settle.
+  pub fn get_duration(&self) -> Option<f64> {
+    match *self {
+      TweenFunction::Spring {stiffness, damping, mass} =>
+        Some(spring_duration(stiffness, damping, mass)),
+      _ => None,
+    }
+  }
+}
+
+/// Solves a CSS style cubic bezier easing curve for y at the given x.
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, x: f32) -> f32 {
+  let (x1, x2) = (x1.max(0.0).min(1.0), x2.max(0.0).min(1.0));
+  // Bezier polynomial coefficients, for p(t) = ((a*t + b)*t + c)*t
+  let cx = 3.0 * x1;
+  let bx = 3.0 * (x2 - x1) - cx;
+  let ax = 1.0 - cx - bx;
+  let cy = 3.0 * y1;
+  let by = 3.0 * (y2 - y1) - cy;
+  let ay = 1.0 - cy - by;
+  let sample_x = |t: f32| ((ax * t + bx) * t + cx) * t;
+
+  // Find t for x with Newton's method, falling back to bisection when the
+  // slope is too flat to converge
+  let mut t = x;
+  let mut solved = false;
+  for _ in 0..8 {
+    let err = sample_x(t) - x;
+    if err.abs() < 1e-6 { solved = true; break; }
+    let slope = (3.0 * ax * t + 2.0 * bx) * t + cx;
+    if slope.abs() < 1e-6 { break; }
+    t -= err / slope;
+  }
+  if !solved || t < 0.0 || t > 1.0 {
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    t = x;
+    for _ in 0..32 {
+      let sx = sample_x(t);
+      if (sx - x).abs() < 1e-6 { break; }
+      if sx < x { lo = t; } else { hi = t; }
+      t = (lo + hi) * 0.5;
+    }
+  }
+  ((ay * t + by) * t + cy) * t
+}
+
+/// Ease out bounce, like a ball dropped onto the end.
+fn bounce(x: f32) -> f32 {
+  let n = 7.5625;
+  let d = 2.75;
+  if x < 1.0 / d {
+    n * x * x
+  }
+  else if x < 2.0 / d {
+    let x = x - 1.5 / d;
+    n * x * x + 0.75
+  }
+  else if x < 2.5 / d {
+    let x = x - 2.25 / d;
+    n * x * x + 0.9375
+  }
+  else {
+    let x = x - 2.625 / d;
+    n * x * x + 0.984375
+  }
+}
+
+/// Returns the position of a spring moving from 0 to 1 after t seconds,
+/// starting at rest.
+fn spring_position(stiffness: f32, damping: f32, mass: f32, t: f64) -> f64 {
+  let (k, c, m) = (stiffness.max(1e-6) as f64, damping.max(0.0) as f64,
+                   mass.max(1e-6) as f64);
+  let w0 = (k / m).sqrt(); // Undamped angular frequency
+  let zeta = c / (2.0 * (k * m).sqrt()); // Damping ratio
+  if zeta < 1.0 {
+    // Underdamped, oscillates around the end
+    let wd = w0 * (1.0 - zeta * zeta).sqrt();
+    let envelope = (-zeta * w0 * t).exp();
+    1.0 - envelope * ((wd * t).cos() + (zeta * w0 / wd) * (wd * t).sin())
+  }
+  else if zeta == 1.0 {
+    // Critically damped
+    1.0 - (-w0 * t).exp() * (1.0 + w0 * t)
+  }
+  else {
+    // Overdamped, creeps towards the end
+    let root = (zeta * zeta - 1.0).sqrt();
+    let r1 = -w0 * (zeta - root);
+    let r2 = -w0 * (zeta + root);
+    1.0 - (r2 * (r1 * t).exp() - r1 * (r2 * t).exp()) / (r2 - r1)
+  }
+}
+
+/// Returns how long in seconds a spring moving from 0 to 1 takes to settle
+/// within SPRING_SETTLE_TOLERANCE of the end.
+fn spring_duration(stiffness: f32, damping: f32, mass: f32) -> f64 {
+  let (k, c, m) = (stiffness.max(1e-6) as f64, damping.max(0.0) as f64,
+                   mass.max(1e-6) as f64);
+  let w0 = (k / m).sqrt();
+  let zeta = c / (2.0 * (k * m).sqrt());
+  if zeta < 1.0 {
+    // The oscillation is bounded by its decaying envelope
+    if zeta <= 0.0 { return SPRING_MAX_DURATION; }
+    let amplitude = 1.0 / (1.0 - zeta * zeta).sqrt();
+    let t = (amplitude / SPRING_SETTLE_TOLERANCE).ln() / (zeta * w0);
+    return t.min(SPRING_MAX_DURATION);
+  }
+  // Critically and overdamped springs approach the end without passing it,
+  // so step forwards until close enough
+  let step = 1.0 / 240.0;
+  let mut t = 0.0;
+  while t < SPRING_MAX_DURATION {
+    if 1.0 - spring_position(stiffness, damping, mass, t) < SPRING_SETTLE_TOLERANCE {
+      return t;
+    }
+    t += step;
+  }
+  return SPRING_MAX_DURATION;
+}
+
+/// Returns how far through an animation of the given length (in seconds) the
+/// timer is, from 0 to 1. Animations with no length are always complete.
+fn get_progress(anim_len: f64, anim_timer: f64) -> f32 {
+  if anim_len <= 0.0 { return 1.0; }
+  (anim_timer / anim_len).max(0.0).min(1.0) as f32
 }
 
 /// Component for a translation animation. Translate's the entity's AABB over a
@@ -53,30 +237,586 @@ pub struct ComponentAnimTranslate {
   pub start_y: f32,
 
   /// Ending X location. The entity's AABB will end up here after anim_len
-  /// millis.
+  /// seconds.
   pub end_x: f32,
   /// Ending Y location. The entity's AABB will end up here after anim_len
-  /// millis.
+  /// seconds.
   pub end_y: f32,
 
-  /// Animation length in milliseconds
-  pub anim_len: u32,
+  /// Animation length in seconds
+  pub anim_len: f64,
 
-  /// Millisecond timer. Counts up every frame, until it reaches anim_len.
-  pub anim_timer: u32,
+  /// Timer in seconds. Counts up every frame, until it reaches anim_len.
+  pub anim_timer: f64,
 
   /// Tween function
   pub tween_func: TweenFunction,
+
+  /// Velocity in dp per second the entity starts with, in place of the
+  /// tween's own starting velocity. The difference fades out over the
+  /// animation, which still ends at the end location. Carries on the motion
+  /// of an interrupted animation, see Layer::retarget_translate. None to
+  /// follow the tween alone.
+  pub start_velocity: Option<(f32, f32)>,
 }
 impl Component for ComponentAnimTranslate {
   fn get_entity_id(&self) -> EntityID { self.entity_id }
 }
 
 impl ComponentAnimTranslate {
-  /// Returns a tweened f32 value between 0 and 1.
+  /// Returns a tweened f32 value, 0 at the start and 1 at the end. Some tween
+  /// functions overshoot outside of 0 and 1.
   pub fn tween(&self) -> f32 {
-    self.tween_func.tween(self.anim_len, self.anim_timer) as f32 / self.anim_len as f32
+    self.tween_func.tween(get_progress(self.get_len(), self.anim_timer))
+  }
+
+  /// Returns the animation's length in seconds. This is anim_len, unless the
+  /// tween function decides its own length.
+  pub fn get_len(&self) -> f64 {
+    self.tween_func.get_duration().unwrap_or(self.anim_len)
+  }
+
+  /// Returns where the animation puts the entity at the given time in
+  /// seconds from its start.
+  pub fn get_position_at(&self, time: f64) -> (f32, f32) {
+    let len = self.get_len();
+    let x = get_progress(len, time);
+    let t = self.tween_func.tween(x);
+    let mut pos = (self.start_x + (self.end_x - self.start_x) * t,
+                   self.start_y + (self.end_y - self.start_y) * t);
+    if self.start_velocity.is_some() {
+      // Hermite curve: 0 at both ends, with a slope of 1 at the start and 0
+      // at the end. Scaled by the difference between the start velocity and
+      // the tween's, so the entity starts at exactly the start velocity.
+      let (v, tv) = (self.start_velocity.unwrap(), self.get_tween_start_velocity());
+      let h = x * (1.0 - x) * (1.0 - x) * len.max(0.0) as f32;
+      pos.0 += (v.0 - tv.0) * h;
+      pos.1 += (v.1 - tv.1) * h;
+    }
+    return pos;
+  }
+
+  /// Returns the velocity in dp per second the tween alone starts with.
+  fn get_tween_start_velocity(&self) -> (f32, f32) {
+    let len = self.get_len();
+    if len <= 0.0 { return (0.0, 0.0); }
+    let dx = 0.001;
+    let slope = (self.tween_func.tween(dx) - self.tween_func.tween(0.0)) /
+      (dx * len as f32);
+    ((self.end_x - self.start_x) * slope, (self.end_y - self.start_y) * slope)
+  }
+
+  /// Returns the entity's current velocity in dp per second, found from
+  /// where the animation puts it just before and after its timer. rate is
+  /// the speed the animation is playing at, see ComponentAnimPlayback::get_rate.
+  pub fn get_velocity(&self, rate: f64) -> (f32, f32) {
+    if rate == 0.0 { return (0.0, 0.0); }
+    let len = self.get_len();
+    let dt = 0.001;
+    let t0 = (self.anim_timer - dt * 0.5).max(0.0);
+    let t1 = (t0 + dt).min(len);
+    if t1 <= t0 { return (0.0, 0.0); }
+    let (p0, p1) = (self.get_position_at(t0), self.get_position_at(t1));
+    let dt = (t1 - t0) as f32 / rate as f32;
+    ((p1.0 - p0.0) / dt, (p1.1 - p0.1) / dt)
   }
 }
 
+/// A property of an entity which can be animated, with the values to animate
+/// it between.
+#[derive(Clone)]
+pub enum AnimProperty {
+  /// Position of the entity's AABB, in dp.
+  Position { start: (f32, f32), end: (f32, f32) },
+  /// Width of the entity's AABB, in dp. Entities laid out by a container have
+  /// their size set by the layout manager.
+  Width { start: f32, end: f32 },
+  /// Height of the entity's AABB, in dp. Entities laid out by a container
+  /// have their size set by the layout manager.
+  Height { start: f32, end: f32 },
+  /// Fill colour of the entity's ComponentBackground. Does nothing if the
+  /// entity has no background.
+  BackgroundColor { start: RGBAf32, end: RGBAf32 },
+  /// Opacity of the entity's ComponentOpacity, added if the entity doesn't
+  /// have one.
+  Opacity { start: f32, end: f32 },
+  /// Rotation in radians of the entity's ComponentTransform, added if the
+  /// entity doesn't have one.
+  Rotation { start: f32, end: f32 },
+  /// Scale of the entity's ComponentTransform, added if the entity doesn't
+  /// have one.
+  Scale { start: (f32, f32), end: (f32, f32) },
+}
+
+impl AnimProperty {
+  /// Returns true if both animate the same property, whatever their values.
+  pub fn is_same_property(&self, other: &AnimProperty) -> bool {
+    mem::discriminant(self) == mem::discriminant(other)
+  }
+}
+
+/// Animation of a single property of an entity. See ComponentAnimProperty.
+#[derive(Clone)]
+pub struct PropertyAnim {
+  /// The property to animate, and its start and end values. The property is
+  /// set to its start value on the next frame.
+  pub property: AnimProperty,
+
+  /// Animation length in seconds
+  pub anim_len: f64,
+
+  /// Timer in seconds. Counts up every frame, until it reaches anim_len.
+  pub anim_timer: f64,
+
+  /// Tween function
+  pub tween_func: TweenFunction,
+}
+
+impl PropertyAnim {
+  pub fn new(property: AnimProperty, anim_len: f64,
+             tween_func: TweenFunction) -> PropertyAnim {
+    PropertyAnim {
+      property: property,
+      anim_len: anim_len,
+      anim_timer: 0.0,
+      tween_func: tween_func,
+    }
+  }
+
+  /// Returns a tweened f32 value, 0 at the start and 1 at the end. Some tween
+  /// functions overshoot outside of 0 and 1.
+  pub fn tween(&self) -> f32 {
+    self.tween_func.tween(get_progress(self.get_len(), self.anim_timer))
+  }
+
+  /// Returns the animation's length in seconds. This is anim_len, unless the
+  /// tween function decides its own length.
+  pub fn get_len(&self) -> f64 {
+    self.tween_func.get_duration().unwrap_or(self.anim_len)
+  }
+}
 
+/// Component for property animations. Holds every property animation running
+/// on the entity, so several properties can be animated at once, each with
+/// its own length and tween function. The component is removed once all of
+/// its animations have finished.
+#[derive(Clone)]
+pub struct ComponentAnimProperty {
+  pub entity_id: EntityID,
+  pub anims: Vec<PropertyAnim>,
+}
+impl Component for ComponentAnimProperty {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+impl ComponentAnimProperty {
+  pub fn new(entity_id: EntityID) -> ComponentAnimProperty {
+    ComponentAnimProperty {
+      entity_id: entity_id,
+      anims: Vec::new(),
+    }
+  }
+
+  /// Adds an animation, replacing any animation of the same property.
+  /// Returns the animation replaced, if there was one.
+  pub fn add(&mut self, anim: PropertyAnim) -> Option<PropertyAnim> {
+    let index = self.anims.iter().position(|a| a.property.is_same_property(&anim.property));
+    self.anims.push(anim);
+    index.map(|ii| self.anims.remove(ii))
+  }
+}
+
+/// Value of an animatable property at a keyframe. See AnimProperty.
+#[derive(Clone)]
+pub enum KeyValue {
+  Position(f32, f32),
+  Width(f32),
+  Height(f32),
+  BackgroundColor(RGBAf32),
+  Opacity(f32),
+  Rotation(f32),
+  Scale(f32, f32),
+}
+
+impl KeyValue {
+  /// Returns the property animation from this value to the end value, or
+  /// None if they are values of different properties.
+  pub fn to(&self, end: &KeyValue) -> Option<AnimProperty> {
+    match (self, end) {
+      (&KeyValue::Position(x0, y0), &KeyValue::Position(x1, y1)) =>
+        Some(AnimProperty::Position { start: (x0, y0), end: (x1, y1) }),
+      (&KeyValue::Width(a), &KeyValue::Width(b)) =>
+        Some(AnimProperty::Width { start: a, end: b }),
+      (&KeyValue::Height(a), &KeyValue::Height(b)) =>
+        Some(AnimProperty::Height { start: a, end: b }),
+      (&KeyValue::BackgroundColor(ref a), &KeyValue::BackgroundColor(ref b)) =>
+        Some(AnimProperty::BackgroundColor { start: a.clone(), end: b.clone() }),
+      (&KeyValue::Opacity(a), &KeyValue::Opacity(b)) =>
+        Some(AnimProperty::Opacity { start: a, end: b }),
+      (&KeyValue::Rotation(a), &KeyValue::Rotation(b)) =>
+        Some(AnimProperty::Rotation { start: a, end: b }),
+      (&KeyValue::Scale(x0, y0), &KeyValue::Scale(x1, y1)) =>
+        Some(AnimProperty::Scale { start: (x0, y0), end: (x1, y1) }),
+      _ => None,
+    }
+  }
+}
+
+/// A property's value at a point in a keyframe track.
+#[derive(Clone)]
+pub struct Keyframe {
+  /// Time of the keyframe in seconds, from the start of the track.
+  pub time: f64,
+  pub value: KeyValue,
+  /// Tween function used to ease from the previous keyframe to this one.
+  /// Unused for the first keyframe.
+  pub tween_func: TweenFunction,
+}
+
+/// Animates one property of an entity through a list of keyframes, ordered by
+/// time. Before the first keyframe the property has the first keyframe's
+/// value, and after the last it keeps the last keyframe's value. Keyframes
+/// must all be values of the same property.
+#[derive(Clone)]
+pub struct KeyframeTrack {
+  pub keyframes: Vec<Keyframe>,
+}
+
+impl KeyframeTrack {
+  /// Returns the length of the track in seconds, i.e the time of its last
+  /// keyframe.
+  pub fn get_len(&self) -> f64 {
+    self.keyframes.last().map(|k| k.time.max(0.0)).unwrap_or(0.0)
+  }
+
+  /// Returns the property animation for the segment of the track at time t,
+  /// and how far through it the property is.
+  pub fn sample(&self, t: f64) -> Option<(AnimProperty, f32)> {
+    if self.keyframes.len() == 0 { return None; }
+    let first = &self.keyframes[0];
+    if t <= first.time {
+      return first.value.to(&first.value).map(|p| (p, 0.0));
+    }
+    for ii in 1..self.keyframes.len() {
+      let (prev, next) = (&self.keyframes[ii - 1], &self.keyframes[ii]);
+      if t < next.time {
+        let progress = get_progress(next.time - prev.time, t - prev.time);
+        return prev.value.to(&next.value)
+          .map(|p| (p, next.tween_func.tween(progress)));
+      }
+    }
+    let last = self.keyframes.last().unwrap();
+    return last.value.to(&last.value).map(|p| (p, 1.0));
+  }
+}
+
+/// How many times Animation::Repeat plays its animation.
+#[derive(Clone, Copy)]
+pub enum RepeatCount {
+  Times(u32),
+  Infinite,
+}
+
+/// A composable animation, played by a ComponentAnimTimeline. Animations
+/// are functions of time, so the whole timeline can be sampled at any point.
+/// Animations can target any entity in the timeline's layer.
+#[derive(Clone)]
+pub enum Animation {
+  /// Tweens one property of an entity from its start to its end value.
+  Tween {
+    entity_id: EntityID,
+    property: AnimProperty,
+    /// Length in seconds. Ignored if the tween function decides its own
+    /// length.
+    anim_len: f64,
+    tween_func: TweenFunction,
+  },
+  /// Animates one property of an entity through keyframes.
+  Keyframes {
+    entity_id: EntityID,
+    track: KeyframeTrack,
+  },
+  /// Plays each animation once the previous one has finished. Animations
+  /// keep their end values once finished, and aren't applied until they
+  /// start.
+  Sequence(Vec<Animation>),
+  /// Plays all animations at the same time. Lasts as long as the longest.
+  Parallel(Vec<Animation>),
+  /// Does nothing for the given number of seconds. Used in sequences.
+  Delay(f64),
+  /// Plays an animation several times, or forever. If alternate is set,
+  /// every other repeat plays backwards.
+  Repeat {
+    anim: Box<Animation>,
+    count: RepeatCount,
+    alternate: bool,
+  },
+}
+
+impl Animation {
+  /// Returns the length of the animation in seconds, or None if it repeats
+  /// forever.
+  pub fn get_len(&self) -> Option<f64> {
+    match *self {
+      Animation::Tween {anim_len, tween_func, ..} =>
+        Some(tween_func.get_duration().unwrap_or(anim_len).max(0.0)),
+      Animation::Keyframes {ref track, ..} => Some(track.get_len()),
+      Animation::Sequence(ref anims) => {
+        let mut len = 0.0;
+        for a in anims {
+          let l = a.get_len();
+          if l.is_none() { return None; }
+          len += l.unwrap();
+        }
+        Some(len)
+      },
+      Animation::Parallel(ref anims) => {
+        let mut len = 0.0f64;
+        for a in anims {
+          let l = a.get_len();
+          if l.is_none() { return None; }
+          len = len.max(l.unwrap());
+        }
+        Some(len)
+      },
+      Animation::Delay(len) => Some(len.max(0.0)),
+      Animation::Repeat {ref anim, count, ..} => {
+        match count {
+          RepeatCount::Infinite => None,
+          RepeatCount::Times(n) => anim.get_len().map(|l| l * n as f64),
+        }
+      },
+    }
+  }
+
+  /// Samples the animation at t seconds from its start, adding the property
+  /// values it sets to out. Each value is given as the entity, the property's
+  /// animation and how far through it the property is. Later values override
+  /// earlier values of the same property.
+  pub fn sample(&self, t: f64, out: &mut Vec<(EntityID, AnimProperty, f32)>) {
+    match *self {
+      Animation::Tween {entity_id, ref property, anim_len, tween_func} => {
+        let len = tween_func.get_duration().unwrap_or(anim_len);
+        out.push((entity_id, property.clone(), tween_func.tween(get_progress(len, t))));
+      },
+      Animation::Keyframes {entity_id, ref track} => {
+        let value = track.sample(t);
+        if value.is_some() {
+          let (property, tweened) = value.unwrap();
+          out.push((entity_id, property, tweened));
+        }
+      },
+      Animation::Sequence(ref anims) => {
+        let mut start = 0.0;
+        for a in anims {
+          if t < start { break; }
+          a.sample(t - start, out);
+          let len = a.get_len();
+          if len.is_none() { break; }
+          start += len.unwrap();
+        }
+      },
+      Animation::Parallel(ref anims) => {
+        for a in anims {
+          a.sample(t, out);
+        }
+      },
+      Animation::Delay(_) => {},
+      Animation::Repeat {ref anim, count, alternate} => {
+        let len = anim.get_len();
+        if len.is_none() || len.unwrap() <= 0.0 {
+          anim.sample(t, out);
+          return;
+        }
+        let len = len.unwrap();
+        let mut iteration = (t.max(0.0) / len).floor();
+        let mut local = t.max(0.0) - iteration * len;
+        if let RepeatCount::Times(n) = count {
+          if n == 0 { return; }
+          if iteration >= n as f64 {
+            // Finished, hold the end of the last repeat
+            iteration = (n - 1) as f64;
+            local = len;
+          }
+        }
+        if alternate && iteration % 2.0 == 1.0 {
+          local = len - local;
+        }
+        anim.sample(local, out);
+      },
+    }
+  }
+}
+
+/// Component which plays an Animation, such as a sequence of property
+/// animations across several entities of the layer. The component is
+/// removed once the animation finishes, unless it repeats forever.
+#[derive(Clone)]
+pub struct ComponentAnimTimeline {
+  pub entity_id: EntityID,
+  pub animation: Animation,
+  /// Timer in seconds. Counts up every frame.
+  pub anim_timer: f64,
+}
+impl Component for ComponentAnimTimeline {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+impl ComponentAnimTimeline {
+  pub fn new(entity_id: EntityID, animation: Animation) -> ComponentAnimTimeline {
+    ComponentAnimTimeline {
+      entity_id: entity_id,
+      animation: animation,
+      anim_timer: 0.0,
+    }
+  }
+
+  /// Returns true once the animation has played to its end.
+  pub fn is_finished(&self) -> bool {
+    let len = self.animation.get_len();
+    len.is_some() && self.anim_timer >= len.unwrap()
+  }
+}
+
+/// Speed in dp per second below which a decay animation comes to rest.
+pub const DECAY_REST_SPEED: f32 = 5.0;
+
+/// Component for a decay (fling) animation. Moves the entity's AABB with an
+/// initial velocity which friction slows down until the entity comes to rest.
+/// The motion is worked out exactly for each frame's delta, so it is the same
+/// at any frame rate.
+/// Dependencies: 
+/// ComponentAABB
+#[derive(Clone)]
+pub struct ComponentAnimDecay {
+  pub entity_id: EntityID,
+  /// Current velocity in dp per second.
+  pub velocity: (f32, f32),
+  /// How quickly the entity slows down. Each second the velocity is
+  /// multiplied by e^-friction, so 0 never slows down.
+  pub friction: f32,
+  /// Optional area the entity's AABB is kept inside. Bounds on each axis are
+  /// only used if the AABB fits inside them on that axis.
+  pub bounds: Option<Rect>,
+  /// Fraction of its speed the entity keeps when it hits the bounds, and
+  /// bounces back. 0 stops the entity at the bounds.
+  pub bounce: f32,
+}
+impl Component for ComponentAnimDecay {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+impl ComponentAnimDecay {
+  /// Creates a decay animation with no bounds.
+  pub fn new(entity_id: EntityID, velocity: (f32, f32), friction: f32) -> ComponentAnimDecay {
+    ComponentAnimDecay {
+      entity_id: entity_id,
+      velocity: velocity,
+      friction: friction,
+      bounds: None,
+      bounce: 0.0,
+    }
+  }
+
+  /// Returns true once the entity has slowed down enough to be at rest.
+  pub fn is_at_rest(&self) -> bool {
+    self.velocity.0.abs() < DECAY_REST_SPEED && self.velocity.1.abs() < DECAY_REST_SPEED
+  }
+
+  /// Returns how far the entity moves in the next dt seconds, and updates
+  /// the velocity to what it will be after them.
+  pub fn step(&mut self, dt: f64) -> (f32, f32) {
+    let dt = dt.max(0.0) as f32;
+    let friction = self.friction.max(0.0);
+    if friction == 0.0 {
+      return (self.velocity.0 * dt, self.velocity.1 * dt);
+    }
+    // Integral of v * e^(-friction * t) over the frame
+    let decay = (-friction * dt).exp();
+    let travel = (1.0 - decay) / friction;
+    let moved = (self.velocity.0 * travel, self.velocity.1 * travel);
+    self.velocity = (self.velocity.0 * decay, self.velocity.1 * decay);
+    return moved;
+  }
+}
+
+/// Controls how an entity's animations play. Entities without one play their
+/// animations forwards at normal speed. See Layer::pause_animations.
+/// Playback is per entity rather than per animation: an entity has at most
+/// one translation, timeline and decay, and its property animations are
+/// parts of one motion which should stay in step. Animations which need
+/// controlling separately belong on separate entities, e.g a container and
+/// its child.
+#[derive(Clone)]
+pub struct ComponentAnimPlayback {
+  pub entity_id: EntityID,
+  /// If true, the entity's animations are frozen where they are.
+  pub paused: bool,
+  /// Speed the entity's animations play at, where 1 is normal speed.
+  /// Negative rates play animations backwards, finishing them once they are
+  /// back at their start.
+  pub rate: f64,
+}
+impl Component for ComponentAnimPlayback {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+impl ComponentAnimPlayback {
+  /// Plays animations forwards at normal speed.
+  pub fn new(entity_id: EntityID) -> ComponentAnimPlayback {
+    ComponentAnimPlayback {
+      entity_id: entity_id,
+      paused: false,
+      rate: 1.0,
+    }
+  }
+
+  /// Returns the speed the entity's animations are playing at: rate, or 0
+  /// while paused.
+  pub fn get_rate(&self) -> f64 {
+    if self.paused { 0.0 } else { self.rate }
+  }
+}
+
+/// The kind of animation an AnimEvent is about.
+#[derive(Clone)]
+pub enum AnimSource {
+  /// A ComponentAnimTranslate, including the snap animations started by
+  /// ComponentScrollSnap when a finger is released.
+  Translate,
+  /// A property animation of a ComponentAnimProperty, with the property it
+  /// animated.
+  Property(AnimProperty),
+  /// A ComponentAnimTimeline.
+  Timeline,
+  /// A ComponentAnimDecay.
+  Decay,
+}
+
+/// What happened to the animation an AnimEvent is about.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AnimEventKind {
+  /// The animation played to its end and was removed.
+  Completed,
+  /// The animation was replaced or cancelled before it finished. Only
+  /// reported for animations changed through Layer's animation methods (see
+  /// Layer::cancel_animations), not by replacing components directly.
+  Cancelled,
+}
+
+/// Reports that an animation has finished.
+#[derive(Clone)]
+pub struct AnimEvent {
+  /// Entity the animation component belonged to.
+  pub entity_id: EntityID,
+  pub source: AnimSource,
+  pub kind: AnimEventKind,
+}
+
+/// Trait which defines an 'AnimListener', a listener who is called when an
+/// animation completes or is cancelled. See LibState::anim_listeners.
+pub trait AnimListener {
+  /// Called after the frame the animation finished in. A frame is always run
+  /// after animation events, so changes made to views here are drawn.
+  fn on_anim_event(&self, lib_state: &mut LibState, event: &AnimEvent);
+}
@@ -1,7 +1,12 @@
 use layout::Layout;
 use entity::{EntityID, Component};
 use entity::animation::TweenFunction;
-use common::color::RGBf32;
+use common::color::{RGBf32, RGBAf32};
+use common::transform::Affine2;
+use common::rect::Insets;
+use image::nine_patch::NinePatch;
+use renderer::canvas::CustomPainter;
+use std::rc::Rc;
 
 /// Namespace to contain constant bitmasks for ComponentTouchScroll::behaviour_flags.
 pub mod scroll_behaviour {
@@ -21,7 +26,7 @@ pub mod scroll_behaviour {
   pub const INVERTED_Y: u32 = 0x00000010;
 }
 
-/// Position component for entity. Defines a 2D view position and size.
+/// Position component for entity. Defines a 2D view position and size, in dp.
 #[derive(Clone)]
 pub struct ComponentAABB {
   pub entity_id: EntityID,
@@ -34,6 +39,178 @@ impl Component for ComponentAABB {
   fn get_entity_id(&self) -> EntityID { self.entity_id }
 }
 
+/// Transforms how an entity is drawn, on top of its laid out AABB. The
+/// transform is inherited by the entity's container children and by nested
+/// layers belonging to the entity. Input hit-testing is transformed to match.
+/// Dependencies: 
+/// ComponentAABB
+#[derive(Clone)]
+pub struct ComponentTransform {
+  pub entity_id: EntityID,
+  /// Point to scale and rotate around, relative to the AABB. (0.5, 0.5) is
+  /// the centre.
+  pub pivot: (f32, f32),
+  pub scale: (f32, f32),
+  /// Rotation in radians, clockwise.
+  pub rotation: f32,
+  /// Offset in dp, applied after scaling and rotating.
+  pub translate: (f32, f32),
+}
+impl Component for ComponentTransform {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+impl ComponentTransform {
+  /// Creates a transform which does nothing, pivoting around the centre.
+  pub fn new(entity_id: EntityID) -> ComponentTransform {
+    ComponentTransform {
+      entity_id: entity_id,
+      pivot: (0.5, 0.5),
+      scale: (1.0, 1.0),
+      rotation: 0.0,
+      translate: (0.0, 0.0),
+    }
+  }
+
+  /// Returns this transform as a matrix, for an entity with the given AABB.
+  pub fn get_matrix(&self, aabb: &ComponentAABB) -> Affine2 {
+    let (px, py) = (aabb.x + self.pivot.0 * aabb.w, aabb.y + self.pivot.1 * aabb.h);
+    Affine2::translate(-px, -py)
+      .then(&Affine2::scale(self.scale.0, self.scale.1))
+      .then(&Affine2::rotate(self.rotation))
+      .then(&Affine2::translate(px + self.translate.0, py + self.translate.1))
+  }
+}
+
+/// Fades an entity when drawn. The opacity is multiplied with those of the
+/// entity's container parents, and inherited by nested layers belonging to
+/// the entity.
+#[derive(Clone)]
+pub struct ComponentOpacity {
+  pub entity_id: EntityID,
+  /// Opacity from 0 (transparent) to 1 (opaque).
+  pub opacity: f32,
+  /// If true, the entity and its children can't be touched while they are
+  /// fully transparent.
+  pub hide_from_input: bool,
+}
+impl Component for ComponentOpacity {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+/// Soft shadow drawn beneath an entity's background, following the
+/// background's rounded corners.
+/// Dependencies: 
+/// ComponentAABB
+#[derive(Clone)]
+pub struct ComponentShadow {
+  pub entity_id: EntityID,
+  /// Offset of the shadow from the entity, in dp.
+  pub offset: (f32, f32),
+  /// Distance over which the shadow's edge fades out, in dp.
+  pub blur_radius: f32,
+  /// Distance the shadow is grown by (shrunk if negative) before blurring,
+  /// in dp.
+  pub spread: f32,
+  pub color: RGBAf32,
+}
+impl Component for ComponentShadow {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+impl ComponentShadow {
+  /// Creates a shadow for an entity raised the given height (in dp) above
+  /// the surface behind it. Higher entities cast larger, softer shadows which
+  /// fall further below them.
+  pub fn from_elevation(entity_id: EntityID, elevation: f32) -> ComponentShadow {
+    let elevation = elevation.max(0.0);
+    ComponentShadow {
+      entity_id: entity_id,
+      offset: (0.0, elevation * 0.5),
+      blur_radius: elevation,
+      spread: 0.0,
+      color: RGBAf32::new(0.0, 0.0, 0.0, 0.3),
+    }
+  }
+}
+
+/// Draws a nine-patch image stretched to fill the entity's AABB, over its
+/// ComponentBackground. Unless the entity has a ComponentPadding, the
+/// nine-patch's padding is used to lay out the entity's children.
+/// Dependencies: 
+/// ComponentAABB
+#[derive(Clone)]
+pub struct ComponentNinePatch {
+  pub entity_id: EntityID,
+  /// Shared, so many entities can use the same image (and texture).
+  pub nine_patch: Rc<NinePatch>,
+}
+impl Component for ComponentNinePatch {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+/// Space between a container entity's AABB and its children. Layout managers
+/// lay children out inside the AABB minus the padding.
+#[derive(Clone)]
+pub struct ComponentPadding {
+  pub entity_id: EntityID,
+  /// Padding in dp.
+  pub padding: Insets,
+}
+impl Component for ComponentPadding {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+/// Draws custom content, such as charts or icons, with vector paths. Drawn
+/// over the entity's background and nine-patch.
+/// Dependencies: 
+/// ComponentAABB
+#[derive(Clone)]
+pub struct ComponentCustomDraw {
+  pub entity_id: EntityID,
+  pub painter: Rc<CustomPainter>,
+}
+impl Component for ComponentCustomDraw {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+/// Gives an entity the properties of a named style from the current theme.
+/// The style is applied again whenever the theme changes. See style::Theme.
+#[derive(Clone)]
+pub struct ComponentStyle {
+  pub entity_id: EntityID,
+  pub name: String,
+}
+impl Component for ComponentStyle {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+/// Text properties of an entity, used by components which draw text.
+#[derive(Clone)]
+pub struct ComponentTextStyle {
+  pub entity_id: EntityID,
+  pub color: RGBAf32,
+  /// Font family name.
+  pub font: String,
+  /// Font size in dp.
+  pub size: f32,
+}
+impl Component for ComponentTextStyle {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
+impl ComponentTextStyle {
+  /// Black 14dp text in the default font.
+  pub fn new(entity_id: EntityID) -> ComponentTextStyle {
+    ComponentTextStyle {
+      entity_id: entity_id,
+      color: RGBAf32::new(0.0, 0.0, 0.0, 1.0),
+      font: "sans-serif".to_string(),
+      size: 14.0,
+    }
+  }
+}
+
 /// Draws a rectangle at this entity's position over everything else. 50% opacity.
 /// Dependencies: 
 /// ComponentAABB
@@ -46,6 +223,53 @@ impl Component for ComponentDebugDraw {
   fn get_entity_id(&self) -> EntityID { self.entity_id }
 }
 
+/// Gradient used to fill a ComponentBackground. Positions are given relative
+/// to the entity's AABB, where (0, 0) is the top left corner and (1, 1) is the
+/// bottom right corner.
+#[derive(Clone)]
+pub enum Gradient {
+  /// Linear gradient running from start to end. Colours are clamped outside
+  /// of the start and end points.
+  Linear {
+    start: (f32, f32),
+    end: (f32, f32),
+    start_color: RGBAf32,
+    end_color: RGBAf32,
+  },
+  /// Radial gradient, fading from inner_color at the centre to outer_color at
+  /// the radius. The radius is relative to the larger of the AABB's width and
+  /// height.
+  Radial {
+    centre: (f32, f32),
+    radius: f32,
+    inner_color: RGBAf32,
+    outer_color: RGBAf32,
+  },
+}
+
+/// Draws a filled background behind this entity, with an optional border,
+/// rounded corners and gradient. Edges are anti-aliased by the renderer.
+/// Dependencies: 
+/// ComponentAABB
+#[derive(Clone)]
+pub struct ComponentBackground {
+  pub entity_id: EntityID,
+  /// Fill colour. Ignored if a gradient is set.
+  pub color: RGBAf32,
+  /// Optional gradient, replaces the fill colour.
+  pub gradient: Option<Gradient>,
+  /// Width of the border in dp. The border is drawn inside the AABB. 0
+  /// for no border.
+  pub border_width: f32,
+  pub border_color: RGBAf32,
+  /// Corner radii in dp, clockwise from the top left corner. Radii are
+  /// clamped to half of the AABB's smallest side.
+  pub corner_radii: [f32; 4],
+}
+impl Component for ComponentBackground {
+  fn get_entity_id(&self) -> EntityID { self.entity_id }
+}
+
 /// Container component. An entity with this will contain other entities.
 #[derive(Clone)]
 pub struct ComponentContainer {
@@ -127,8 +351,8 @@ pub struct ComponentScrollSnap {
   /// The tween function to use for the snapping animation
   pub tween_func: TweenFunction,
 
-  /// Length of snap tween in millis
-  pub tween_len: u32,
+  /// Length of snap tween in seconds
+  pub tween_len: f64,
 }
 impl Component for ComponentScrollSnap {
   fn get_entity_id(&self) -> EntityID { self.entity_id }
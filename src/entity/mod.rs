@@ -32,6 +32,8 @@ impl EntityID {
   /// Sets the layout of this entity. Any current layout (and subsequently,
   /// children) is/are erased.
   pub fn set_layout(&self, layer: &mut Layer, layout: Layout) {
+    // The entity's children change, so its layer's parent map is stale
+    layer.invalidate_parents();
     // Find this entity's layout (if it exists)
     let comp_container_list = &mut layer.component_container;
     {
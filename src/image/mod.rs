@@ -0,0 +1,141 @@
+use std::io::{Read, Write};
+use png;
+use png::HasParameters;
+
+/// Images which stretch without distorting their edges, for backgrounds.
+pub mod nine_patch;
+
+/// An 8 bit RGBA image, stored row by row from the top left.
+#[derive(Clone)]
+pub struct Image {
+  pub width: u32,
+  pub height: u32,
+  /// Pixel data, 4 bytes per pixel.
+  pub data: Vec<u8>,
+}
+
+/// Returns the number of bytes of pixel data in an image of the given size,
+/// or None if it's too large to address.
+fn get_data_len(width: u32, height: u32) -> Option<usize> {
+  (width as usize).checked_mul(height as usize)
+    .and_then(|n| n.checked_mul(4))
+}
+
+impl Image {
+  pub fn new(width: u32, height: u32, data: Vec<u8>) -> Image {
+    assert_eq!(Some(data.len()), get_data_len(width, height));
+    Image {
+      width: width,
+      height: height,
+      data: data,
+    }
+  }
+
+  /// Decodes a PNG. Any colour type is accepted, and converted to 8 bit RGBA.
+  /// Images too large to address are rejected with an error.
+  pub fn from_png<R: Read>(r: R) -> Result<Image, png::DecodingError> {
+    let mut decoder = png::Decoder::new(r);
+    // Expands palettes, low bit depths and transparency chunks, so that only
+    // the colour type and 8/16 bit samples need handling below
+    decoder.set(png::TRANSFORM_EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+    let data_len = get_data_len(info.width, info.height);
+    if data_len.is_none() {
+      return Err(png::DecodingError::Other("image too large".into()));
+    }
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+
+    let channels = match info.color_type {
+      png::ColorType::Grayscale => 1,
+      png::ColorType::GrayscaleAlpha => 2,
+      png::ColorType::RGB => 3,
+      _ => 4,
+    };
+    // 16 bit samples are big endian, keep the most significant byte
+    let sample_size = if info.bit_depth == png::BitDepth::Sixteen { 2 } else { 1 };
+    let pixel_size = channels * sample_size;
+    let mut data = Vec::with_capacity(data_len.unwrap());
+    for row in 0..info.height as usize {
+      let line = &buf[row * info.line_size..];
+      for col in 0..info.width as usize {
+        let p = &line[col * pixel_size..];
+        let sample = |ii: usize| p[ii * sample_size];
+        let rgba = match channels {
+          1 => (sample(0), sample(0), sample(0), 255),
+          2 => (sample(0), sample(0), sample(0), sample(1)),
+          3 => (sample(0), sample(1), sample(2), 255),
+          _ => (sample(0), sample(1), sample(2), sample(3)),
+        };
+        data.push(rgba.0);
+        data.push(rgba.1);
+        data.push(rgba.2);
+        data.push(rgba.3);
+      }
+    }
+    Ok(Image::new(info.width, info.height, data))
+  }
+
+  /// Returns the (r, g, b, a) value of the pixel at x, y.
+  pub fn get_pixel(&self, x: u32, y: u32) -> (u8, u8, u8, u8) {
+    let ii = (y as usize * self.width as usize + x as usize) * 4;
+    (self.data[ii], self.data[ii + 1], self.data[ii + 2], self.data[ii + 3])
+  }
+
+  /// Returns a copy of part of this image. The area is clamped to the image's
+  /// bounds.
+  pub fn crop(&self, x: u32, y: u32, w: u32, h: u32) -> Image {
+    let x0 = x.min(self.width);
+    let y0 = y.min(self.height);
+    let x1 = x.saturating_add(w).min(self.width);
+    let y1 = y.saturating_add(h).min(self.height);
+    // The area is within the image, so its size can't overflow
+    let mut data = Vec::with_capacity(get_data_len(x1 - x0, y1 - y0).unwrap());
+    for row in y0..y1 {
+      let row_start = row as usize * self.width as usize;
+      let start = (row_start + x0 as usize) * 4;
+      let end = (row_start + x1 as usize) * 4;
+      data.extend_from_slice(&self.data[start..end]);
+    }
+    Image::new(x1 - x0, y1 - y0, data)
+  }
+
+  /// Encodes this image as a PNG, writing it to w.
+  pub fn write_png<W: Write>(&self, w: W) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(w, self.width, self.height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&self.data)
+  }
+
+  /// Encodes this image as a PNG, returning the encoded bytes.
+  pub fn to_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+    let mut bytes = Vec::new();
+    self.write_png(&mut bytes)?;
+    Ok(bytes)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn crop_clamps_large_areas() {
+    let data = (0..4 * 4 * 4).map(|ii| ii as u8).collect();
+    let image = Image::new(4, 4, data);
+    let cropped = image.crop(2, 1, u32::max_value(), u32::max_value());
+    assert_eq!((cropped.width, cropped.height), (2, 3));
+    assert_eq!(cropped.get_pixel(0, 0), image.get_pixel(2, 1));
+    assert_eq!(cropped.get_pixel(1, 2), image.get_pixel(3, 3));
+    let empty = image.crop(u32::max_value(), 0, 10, 10);
+    assert_eq!((empty.width, empty.height, empty.data.len()), (0, 4, 0));
+  }
+
+  #[test]
+  fn data_len_rejects_overflow() {
+    assert_eq!(get_data_len(4, 3), Some(48));
+    assert_eq!(get_data_len(u32::max_value(), u32::max_value()), None);
+    assert_eq!(get_data_len(0, u32::max_value()), Some(0));
+  }
+}
@@ -0,0 +1,115 @@
+use std::cell::RefCell;
+use std::io::Read;
+use std::rc::Rc;
+use glium::texture::Texture2d;
+use png;
+
+use common::rect::Insets;
+use image::Image;
+
+/// Errors from loading a nine-patch.
+#[derive(Debug)]
+pub enum NinePatchError {
+  /// The PNG couldn't be decoded.
+  Decoding(png::DecodingError),
+  /// The image is missing the stretch markers in its 1 pixel border, or is
+  /// too small to have a border.
+  MissingMarkers,
+}
+
+impl From<png::DecodingError> for NinePatchError {
+  fn from(e: png::DecodingError) -> NinePatchError {
+    NinePatchError::Decoding(e)
+  }
+}
+
+/// An image which is split into 9 parts so that it can be stretched to any
+/// size: the corners are drawn unscaled, the edges are stretched along one
+/// axis and the centre is stretched along both.
+pub struct NinePatch {
+  pub image: Image,
+  /// Size of the unstretched edges, in image pixels.
+  pub insets: Insets,
+  /// Insets of the area content is placed in, in image pixels.
+  pub padding: Insets,
+  /// Number of image pixels per dp. E.g an image made for a 2x screen should
+  /// have a density of 2.
+  pub density: f32,
+  /// The image's texture, created by the renderer the first time it is drawn.
+  texture: RefCell<Option<Rc<Texture2d>>>,
+}
+
+impl NinePatch {
+  /// Creates a nine-patch from an image and explicit insets, in image pixels.
+  pub fn new(image: Image, insets: Insets, padding: Insets) -> NinePatch {
+    NinePatch {
+      image: image,
+      insets: insets,
+      padding: padding,
+      density: 1.0,
+      texture: RefCell::new(None),
+    }
+  }
+
+  /// Decodes an Android style .9.png. See from_image.
+  pub fn from_png<R: Read>(r: R) -> Result<NinePatch, NinePatchError> {
+    let image = Image::from_png(r)?;
+    NinePatch::from_image(&image)
+  }
+
+  /// Creates a nine-patch from an image with an Android style 1 pixel border.
+  /// Black pixels along the top and left edges mark the stretchable area, and
+  /// along the bottom and right edges mark the content area. If the content
+  /// area isn't marked, it is the same as the stretchable area. Android allows
+  /// several stretchable areas per edge, these are treated as one area from
+  /// the first marker to the last.
+  pub fn from_image(image: &Image) -> Result<NinePatch, NinePatchError> {
+    if image.width < 3 || image.height < 3 {
+      return Err(NinePatchError::MissingMarkers);
+    }
+    let (w, h) = (image.width, image.height);
+    let top = find_markers(image, (1..w - 1).map(|x| (x, 0)).collect());
+    let left = find_markers(image, (1..h - 1).map(|y| (0, y)).collect());
+    if top.is_none() || left.is_none() {
+      return Err(NinePatchError::MissingMarkers);
+    }
+    let bottom = find_markers(image, (1..w - 1).map(|x| (x, h - 1)).collect())
+      .or(top);
+    let right = find_markers(image, (1..h - 1).map(|y| (w - 1, y)).collect())
+      .or(left);
+
+    // Marker spans are inclusive, in the coordinates of the cropped image
+    let (cw, ch) = (w - 2, h - 2);
+    let to_insets = |x: (u32, u32), y: (u32, u32)| Insets::new(
+      x.0 as f32, y.0 as f32, (cw - 1 - x.1) as f32, (ch - 1 - y.1) as f32);
+    Ok(NinePatch::new(image.crop(1, 1, cw, ch),
+                      to_insets(top.unwrap(), left.unwrap()),
+                      to_insets(bottom.unwrap(), right.unwrap())))
+  }
+
+  /// Returns the padding in dp.
+  pub fn get_padding(&self) -> Insets {
+    self.padding.scale(1.0 / self.density)
+  }
+
+  /// Returns the texture cache, used by the renderer.
+  pub fn get_texture_cache(&self) -> &RefCell<Option<Rc<Texture2d>>> {
+    &self.texture
+  }
+}
+
+/// Finds the first and last marker pixels (opaque black) out of the given
+/// pixels, returning their indices in the list minus one, i.e their positions
+/// in the image without its border.
+fn find_markers(image: &Image, pixels: Vec<(u32, u32)>) -> Option<(u32, u32)> {
+  let mut span : Option<(u32, u32)> = None;
+  for (ii, &(x, y)) in pixels.iter().enumerate() {
+    if image.get_pixel(x, y) != (0, 0, 0, 255) { continue; }
+    let ii = ii as u32;
+    span = Some(match span {
+      Some((first, _)) => (first, ii),
+      None => (ii, ii),
+    });
+  }
+  return span;
+}
@@ -1,16 +1,29 @@
 use LibState;
+use ffi_glue;
 use glium::glutin::{Event, TouchPhase};
 use entity::EntityID;
-use entity::core::ComponentScrollSnap;
-use entity::animation::ComponentAnimTranslate;
-use view::{View, Layer};
-use common::vec;
-use std;
+use entity::core::scroll_behaviour;
+use entity::animation::ComponentAnimDecay;
+use view::{View, Layer, Inherited};
+use common::rect::Rect;
+use std::mem;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
 
 mod scroll;
 
 const NUM_POINTS_TRACKED : usize = 128;
 
+/// Only touch points from this long before the finger was raised are used to
+/// estimate its velocity, in nanoseconds.
+const VELOCITY_WINDOW_NANOS : u64 = 100000000;
+
+/// Slowest release speed in dp per second which flings an entity.
+const MIN_FLING_SPEED : f32 = 50.0;
+
+/// Friction of flung entities, see ComponentAnimDecay::friction.
+const FLING_FRICTION : f32 = 4.0;
+
 /// A position on screen touched by a finger
 #[derive(Clone, Copy)]
 pub struct TouchPoint (f64, f64);
@@ -21,6 +34,10 @@ pub struct FingerTrack {
   start_point: TouchPoint,
   /// Array of points, last NUM_POINTS_TRACKED sampled positions
   points: [TouchPoint; NUM_POINTS_TRACKED],
+  /// Clock time each point was sampled at in nanoseconds, see LibState::set_clock
+  point_times: [u64; NUM_POINTS_TRACKED],
+  /// Number of points sampled, up to NUM_POINTS_TRACKED
+  num_points: usize,
   /// Index into points which indicates the most recently updated point. This
   /// is so that every time we add a point to the list, we don't have to shift
   /// the whole vec back one.
@@ -34,12 +51,13 @@ pub struct FingerTrack {
 }
 
 impl FingerTrack {
-  fn new(view: &View, finger_id : u64, loc: (f64, f64)) -> FingerTrack {
+  fn new(view: &View, finger_id : u64, loc: (f64, f64), now: u64) -> FingerTrack {
     let mut entity_id = None;
     let mut offset = None;
     for layer in &view.layers {
-      let result = scroll::is_on_entity_drag_trigger(layer, 
-                                                    loc.0 as f32, loc.1 as f32);
+      let result = scroll::is_on_entity_drag_trigger(layer,
+                                                    loc.0 as f32, loc.1 as f32,
+                                                    &Inherited::root());
       if result.is_some() {
         let result = result.unwrap();
         entity_id = Some(result.0);
@@ -52,35 +70,190 @@ impl FingerTrack {
       finger_id: finger_id,
       start_point: TouchPoint(loc.0, loc.1),
       points: [TouchPoint(0.0, 0.0); NUM_POINTS_TRACKED],
+      point_times: [0; NUM_POINTS_TRACKED],
+      num_points: 1,
       latest_point: 0,
       curr_dragging: entity_id,
       offset: offset,
     };
     track.points[0] = TouchPoint(loc.0, loc.1);
+    track.point_times[0] = now;
     return track;
   }
+
+  /// Adds a point sampled at the given clock time, replacing the oldest point
+  /// if the ring buffer is full.
+  fn push_point(&mut self, point: TouchPoint, now: u64) {
+    self.latest_point += 1;
+    if self.latest_point >= NUM_POINTS_TRACKED {
+      self.latest_point = 0;
+    }
+    self.points[self.latest_point] = point;
+    self.point_times[self.latest_point] = now;
+    self.num_points = (self.num_points + 1).min(NUM_POINTS_TRACKED);
+  }
+
+  /// Estimates the finger's velocity in dp per second at the given clock
+  /// time, by fitting a line through its recent points. Returns (0, 0) if the
+  /// finger hasn't moved recently.
+  fn estimate_velocity(&self, now: u64) -> (f32, f32) {
+    // Collect recent points, newest first
+    let mut samples = Vec::new();
+    for ii in 0..self.num_points {
+      let index = (self.latest_point + NUM_POINTS_TRACKED - ii) % NUM_POINTS_TRACKED;
+      let t = self.point_times[index];
+      if now.saturating_sub(t) > VELOCITY_WINDOW_NANOS { break; }
+      // Seconds before now
+      let age = now.saturating_sub(t) as f64 / 1000000000.0;
+      samples.push((-age, self.points[index]));
+    }
+    if samples.len() < 2 { return (0.0, 0.0); }
+
+    // Least squares fit of position against time on each axis
+    let n = samples.len() as f64;
+    let mean_t = samples.iter().map(|s| s.0).sum::<f64>() / n;
+    let mean_x = samples.iter().map(|s| (s.1).0).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| (s.1).1).sum::<f64>() / n;
+    let (mut var_t, mut cov_x, mut cov_y) = (0.0, 0.0, 0.0);
+    for &(t, p) in &samples {
+      var_t += (t - mean_t) * (t - mean_t);
+      cov_x += (t - mean_t) * (p.0 - mean_x);
+      cov_y += (t - mean_t) * (p.1 - mean_y);
+    }
+    // Points sampled at (almost) the same time can't give a velocity
+    if var_t < 1e-7 { return (0.0, 0.0); }
+    ((cov_x / var_t) as f32, (cov_y / var_t) as f32)
+  }
+}
+
+/// Finds the layer containing an entity's ComponentTouchScroll, searching
+/// nested layers too. Returns the layer with the state it inherits.
+fn find_scroll_layer(layer: &mut Layer, e_id: EntityID,
+                     inherited: Inherited) -> Option<(&mut Layer, Inherited)> {
+  if layer.component_touch_scroll.get_component(e_id).is_some() {
+    return Some((layer, inherited));
+  }
+  let nested_inherited : Vec<Inherited> = layer.component_layer.into_iter()
+    .map(|l| layer.get_nested_inherited(l, &inherited)).collect();
+  for (l, i) in (&mut layer.component_layer).into_iter().zip(nested_inherited.into_iter()) {
+    let found = find_scroll_layer(l, e_id, i);
+    if found.is_some() { return found; }
+  }
+  return None;
+}
+
+/// Called when the finger scrolling an entity is raised, with the finger's
+/// velocity in view coordinates. Unless the entity is STICKY, a fast enough
+/// release flings it, gliding to a stop within its scroll bounds. Entities
+/// with a ComponentScrollSnap snap once they stop.
+fn release_scroll(layer: &mut Layer, e_id: EntityID, finger_velocity: (f32, f32),
+                  inherited: &Inherited) {
+  let (flags, bounds) = {
+    let scroll = layer.component_touch_scroll.get_component(e_id).unwrap();
+    (scroll.behaviour_flags,
+     Rect::new(scroll.min_x, scroll.min_y,
+               scroll.max_x - scroll.min_x, scroll.max_y - scroll.min_y))
+  };
+
+  // Map the velocity into the space the entity's AABB is in, ignoring
+  // translation
+  let mut velocity = (0.0, 0.0);
+  let inverse = layer.get_parent_transform(e_id, &inherited.transform).inverse();
+  if inverse.is_some() && flags & scroll_behaviour::STICKY == 0 {
+    let inverse = inverse.unwrap();
+    let (x0, y0) = inverse.apply(0.0, 0.0);
+    let (x1, y1) = inverse.apply(finger_velocity.0, finger_velocity.1);
+    velocity = (x1 - x0, y1 - y0);
+  }
+  if flags & scroll_behaviour::LOCKED_X > 0 { velocity.0 = 0.0; }
+  if flags & scroll_behaviour::INVERTED_X > 0 { velocity.0 = -velocity.0; }
+  if flags & scroll_behaviour::LOCKED_Y > 0 { velocity.1 = 0.0; }
+  if flags & scroll_behaviour::INVERTED_Y > 0 { velocity.1 = -velocity.1; }
+
+  let speed = (velocity.0 * velocity.0 + velocity.1 * velocity.1).sqrt();
+  if speed >= MIN_FLING_SPEED {
+    // Snapping happens once the glide comes to rest
+    let mut decay = ComponentAnimDecay::new(e_id, velocity, FLING_FRICTION);
+    decay.bounds = Some(bounds);
+    layer.add_anim_decay(decay);
+  }
+  else {
+    layer.start_scroll_snap(e_id);
+  }
 }
 
 pub struct InputState {
   fingers : Vec<FingerTrack>,
+  /// Receiver for android lifecycle events (window resizes, configuration
+  /// changes...), which glutin doesn't forward. None on other platforms.
+  platform_events : Option<Receiver<ffi_glue::Event>>,
+  /// Platform events received by wait_for_events, handled by the next call
+  /// to process_input.
+  pending_platform_events : Vec<ffi_glue::Event>,
 }
 
 impl InputState {
   pub fn new() -> InputState {
     InputState {
       fingers: Vec::new(),
+      platform_events: register_platform_events(),
+      pending_platform_events: Vec::new(),
     }
   }
 }
 
+/// Registers a receiver with the android glue for lifecycle events.
+#[cfg(target_os = "android")]
+fn register_platform_events() -> Option<Receiver<ffi_glue::Event>> {
+  let (sender, receiver) = ::std::sync::mpsc::channel();
+  ffi_glue::add_sender(sender);
+  Some(receiver)
+}
+
+#[cfg(not(target_os = "android"))]
+fn register_platform_events() -> Option<Receiver<ffi_glue::Event>> {
+  None
+}
+
+/// Blocks until the platform sends an event or the timeout passes, None
+/// waits forever. The android glue forwards touches to the platform receiver
+/// as well as to glutin, so any input wakes the caller. The event is kept for
+/// the next call to process_input. Returns false without waiting if there's
+/// no platform receiver, in which case the caller has to poll.
+pub fn wait_for_events(lib_state: &mut LibState, timeout: Option<Duration>)
+  -> bool {
+  let input_state = &mut lib_state.input_state;
+  if input_state.platform_events.is_none() { return false; }
+  let receiver = input_state.platform_events.as_ref().unwrap();
+  let event = match timeout {
+    Some(t) => receiver.recv_timeout(t).ok(),
+    None => receiver.recv().ok(),
+  };
+  if event.is_some() {
+    input_state.pending_platform_events.push(event.unwrap());
+  }
+  return true;
+}
+
 /// Function which polls and processes input. When an input event is received
 /// from Glutin, this function processes the event then continues waiting for
-/// another event.
-pub fn process_input(lib_state: &mut LibState) {
+/// another event. Returns true if any touch or window event was received.
+pub fn process_input(lib_state: &mut LibState) -> bool {
+  let mut had_events = false;
+  // New window size, if the window has been resized
+  let mut resized = None;
   'Outer:
   for e in lib_state.display.poll_events() {
     match e {
+      Event::Resized(w, h) => {
+        had_events = true;
+        resized = Some((w, h));
+      }
       Event::Touch(touch) => {
+        had_events = true;
+        // Convert the touch location from physical pixels to dp
+        let scale = lib_state.dpi_scale as f64;
+        let location = (touch.location.0 / scale, touch.location.1 / scale);
         // Find the index of the finger
         let mut index : Option<usize> = None;
         for ii in 0..lib_state.input_state.fingers.len() {
@@ -94,90 +267,51 @@ pub fn process_input(lib_state: &mut LibState) {
           // New touch event!
           let curr_view = lib_state.view_stack.last();
           if curr_view.is_none() { continue 'Outer; }
-          lib_state.input_state.fingers.push(
-            FingerTrack::new(curr_view.unwrap(), touch.id, touch.location));
+          let track = FingerTrack::new(curr_view.unwrap(), touch.id, location,
+                                       lib_state.clock.now_nanos());
+          // Catch the entity if it is gliding or snapping
+          if track.curr_dragging.is_some() {
+            let e_id = track.curr_dragging.unwrap();
+            for l in &mut lib_state.view_stack.last_mut().unwrap().layers {
+              let found = find_scroll_layer(l, e_id, Inherited::root());
+              if found.is_some() {
+                found.unwrap().0.cancel_motion(e_id);
+                break;
+              }
+            }
+          }
+          lib_state.input_state.fingers.push(track);
           continue;
         }
         else if index.is_some() {
           let index = index.unwrap();
           if touch.phase == TouchPhase::Moved {
-            lib_state.input_state.fingers[index].latest_point += 1;
-            if lib_state.input_state.fingers[index].latest_point 
-              >= NUM_POINTS_TRACKED {
-                lib_state.input_state.fingers[index].latest_point = 0;
-              }
+            let now = lib_state.clock.now_nanos();
             lib_state.input_state.fingers[index]
-              .points[lib_state.input_state.fingers[index].latest_point] 
-              = TouchPoint(touch.location.0, touch.location.1);
+              .push_point(TouchPoint(location.0, location.1), now);
           }
           else if touch.phase == TouchPhase::Ended ||
             touch.phase == TouchPhase::Cancelled {
-              // Before removing, execute any ComponentScrollSnap behaviour
-              let finger = lib_state.input_state.fingers.remove(index);
+              // Before removing, fling the entity or execute any
+              // ComponentScrollSnap behaviour
+              let now = lib_state.clock.now_nanos();
+              let mut finger = lib_state.input_state.fingers.remove(index);
+              finger.push_point(TouchPoint(location.0, location.1), now);
               if finger.curr_dragging.is_some() {
                 let e_id = finger.curr_dragging.unwrap();
                 let curr_view = lib_state.view_stack.last_mut();
                 if curr_view.is_none() { continue 'Outer; }
                 let curr_view = curr_view.unwrap();
 
-                // Create a recursive function to search through all layers
-                fn find_snap_in_layer(layer: &mut Layer, 
-                                      e_id: EntityID) -> Option<(&mut Layer, ComponentScrollSnap)> {
-                  let mut snap : Option<ComponentScrollSnap> = None;
-                  {
-                    let snap_opt = layer.component_scroll_snap.get_component(e_id);
-                    if snap_opt.is_some() { 
-                      snap = Some(snap_opt.unwrap().clone());
-                    }
-                  }
-                  if snap.is_some() {
-                    return Some((layer, snap.unwrap())); 
-                  }
-                  for l in &mut layer.component_layer {
-                    let snap = find_snap_in_layer(l, e_id);
-                    if snap.is_some() { return snap; }
-                  }
-                  return None;
-                }
-
-                let mut snap = None;
-                // Call find_snap_in_layer for each layer in this view
+                let mut found = None;
                 for l in &mut curr_view.layers {
-                  snap = find_snap_in_layer(l, e_id);
-                  if snap.is_some() { break; }
+                  found = find_scroll_layer(l, e_id, Inherited::root());
+                  if found.is_some() { break; }
                 }
-                if snap.is_none() { continue 'Outer; }
-                let snap = snap.unwrap();
-                let layer = snap.0; // Get the layer we found the snap component on
-                let snap = snap.1; // Get the snap component
-
-                // Find AABB
-                let aabb = layer.component_aabb.get_component(e_id);
-                if aabb.is_none() { continue 'Outer; }
-                let aabb = aabb.unwrap();
-
-                // Find the closest snap point
-                let mut closest = None;
-                let mut shortest = std::f32::MAX;
-                for pos in &snap.snap_positions {
-                  let dis = vec::sq_distance(*pos, (aabb.x, aabb.y));
-                  if closest.is_none() || shortest > dis {
-                    closest = Some(pos);
-                    shortest = dis;
-                  }
-                }
-                if closest.is_none() { continue 'Outer; }
-                let closest = closest.unwrap();
-
-                // Add a snap animation
-                layer.component_anim_translate.add_component(
-                  ComponentAnimTranslate {
-                    entity_id: e_id,
-                    start_x: aabb.x, start_y: aabb.y,
-                    end_x: closest.0, end_y: closest.1,
-                    anim_len: snap.tween_len,
-                    anim_timer: 0,
-                    tween_func: snap.tween_func, });
+                if found.is_none() { continue 'Outer; }
+                let (layer, inherited) = found.unwrap();
+                let velocity = finger.estimate_velocity(now);
+                release_scroll(layer, e_id, velocity, &inherited);
               }
               continue;
             }
@@ -187,6 +321,49 @@ pub fn process_input(lib_state: &mut LibState) {
     }
   }
 
+  // Check for resizes and orientation changes from the platform. These events
+  // don't carry the new size, so query the window for it.
+  let mut config_changed = false;
+  let mut redraw_needed = false;
+  let mut events = mem::replace(
+    &mut lib_state.input_state.pending_platform_events, Vec::new());
+  if lib_state.input_state.platform_events.is_some() {
+    let receiver = lib_state.input_state.platform_events.as_ref().unwrap();
+    while let Ok(e) = receiver.try_recv() {
+      events.push(e);
+    }
+  }
+  for e in events {
+    had_events = true;
+    match e {
+      ffi_glue::Event::WindowResized => {
+        let size = lib_state.query_window_size();
+        if size.is_some() { resized = size; }
+      }
+      ffi_glue::Event::ConfigChanged => config_changed = true,
+      // Animations are frozen while the app is in the background
+      ffi_glue::Event::Pause => lib_state.platform_paused = true,
+      ffi_glue::Event::Resume => lib_state.platform_paused = false,
+      // The window's contents may have been lost
+      ffi_glue::Event::InitWindow | ffi_glue::Event::WindowRedrawNeeded |
+      ffi_glue::Event::GainedFocus => redraw_needed = true,
+      _ => continue,
+    }
+  }
+  if redraw_needed && lib_state.renderer.is_some() {
+    lib_state.renderer.as_mut().unwrap().invalidate();
+  }
+  if config_changed {
+    // Configuration changes can change the density as well as the size
+    lib_state.reload_dpi_scale();
+    let size = lib_state.query_window_size();
+    if size.is_some() { resized = size; }
+  }
+  if resized.is_some() {
+    let (w, h) = resized.unwrap();
+    lib_state.resize(w, h);
+  }
+
   // Check there is a current view
   let mut has_scrolled = false;
   let curr_view = lib_state.view_stack.last_mut();
@@ -195,11 +372,13 @@ pub fn process_input(lib_state: &mut LibState) {
     let curr_view = curr_view.unwrap();
     for layer in &mut curr_view.layers {
       has_scrolled = 
-        if scroll::process_scroll(layer, &lib_state.input_state) {true} 
+        if scroll::process_scroll(layer, &lib_state.input_state,
+                                  &Inherited::root()) {true} 
         else {has_scrolled};
     }
     if has_scrolled {
       //curr_view.layout();
     }
   }
+  return had_events;
 }
@@ -1,7 +1,9 @@
 use view::Layer;
-use input::InputState;
+use input::{InputState, TouchPoint};
 use entity::EntityID;
 use entity::core::ComponentTrigger;
+use common::transform::Affine2;
+use view::Inherited;
 use logger;
 
 
@@ -26,17 +28,43 @@ fn get_entity_trigger(layer: &Layer,
 
 /// Tests if the given position is on an entity's drag trigger.
 /// Returns the entity ID of the entity touched, and the offset of the touch.
-pub fn is_on_entity_drag_trigger(layer: &Layer, 
-                                 x: f32, y: f32) -> Option<(EntityID, (f32, f32))> {
+/// Positions are in view coordinates, the same as every layer's AABBs (see
+/// view::Layer). Nested layers are drawn on top of their parent, so they are
+/// tested first, and only if the position is inside their clip rect.
+/// Entity transforms are undone before testing, with inherited being the
+/// state inherited by this layer. The offset is in the space of the entity's
+/// AABB. Transparent entities with ComponentOpacity::hide_from_input are
+/// skipped.
+pub fn is_on_entity_drag_trigger(layer: &Layer, x: f32, y: f32,
+                                 inherited: &Inherited) -> Option<(EntityID, (f32, f32))> {
+  let transform = &inherited.transform;
+  // Look in nested layers first
+  for l in layer.component_layer.into_iter().rev() {
+    let clip = layer.get_nested_clip_rect(l);
+    if clip.is_none() { continue; }
+    if !layer.is_hittable(l.entity_id.unwrap(), inherited.opacity) { continue; }
+    let nested_inherited = layer.get_nested_inherited(l, inherited);
+    let inverse = nested_inherited.transform.inverse();
+    if inverse.is_none() { continue; }
+    let (lx, ly) = inverse.unwrap().apply(x, y);
+    if !clip.unwrap().contains(lx, ly) { continue; }
+    let res = is_on_entity_drag_trigger(l, x, y, &nested_inherited);
+    if res.is_some() { return res; }
+  }
+
   logger::log_default("Testing if entity is on a drag trigger...");
   for scroll in &layer.component_touch_scroll {
     let e_id = scroll.entity_id;
     logger::log_default("Found a scroll component");
+    if !layer.is_hittable(e_id, inherited.opacity) { continue; }
     // Find trigger box position
     let trigger_box = get_entity_trigger(layer, e_id);
     if trigger_box.is_none() { continue; }
     logger::log_default("Found a drag trigger");
     let (tx, ty, tw, th) = trigger_box.unwrap();
+    let inverse = layer.get_entity_transform(e_id, transform).inverse();
+    if inverse.is_none() { continue; }
+    let (x, y) = inverse.unwrap().apply(x, y);
     // Check if the x & y are inside the trigger AABB
     logger::log_default(&format!("AABB: {}, {}, {}, {} - X, Y: {}, {}",
                                  tx, ty, tw, th, x, y));
@@ -46,21 +74,31 @@ pub fn is_on_entity_drag_trigger(layer: &Layer,
       let aabb = layer.component_aabb.get_component(e_id);
       if aabb.is_none() { continue; }
       let aabb = aabb.unwrap();
+      // The offset is found in the space the AABB is in, i.e without the
+      // entity's own transform, so dragging moves the AABB with the finger
+      let (x, y) = layer.get_entity_transform(e_id, transform).apply(x, y);
+      let inverse = layer.get_parent_transform(e_id, transform).inverse();
+      if inverse.is_none() { continue; }
+      let (x, y) = inverse.unwrap().apply(x, y);
       return Some((e_id, (x - aabb.x, y - aabb.y)));
     }
   }
 
-  // Recursively look in nested layers
-  for layer in &layer.component_layer {
-    let res = is_on_entity_drag_trigger(layer, x, y);
-    if res.is_some() { return res; }
-  }
-
   return None;
 }
 
-/// Process scrolling. Returns true if scrolling has happened.
-pub fn process_scroll(layer: &mut Layer, input_state: &InputState) -> bool {
+/// Maps a touch point through a transform.
+fn transform_point(transform: &Affine2, p: TouchPoint) -> TouchPoint {
+  let (x, y) = transform.apply(p.0 as f32, p.1 as f32);
+  TouchPoint(x as f64, y as f64)
+}
+
+/// Process scrolling. Returns true if scrolling has happened. Touches are
+/// mapped into the space of each dragged entity's AABB, undoing the transforms
+/// of its parents, with inherited being the state inherited by this layer.
+pub fn process_scroll(layer: &mut Layer, input_state: &InputState,
+                      inherited: &Inherited) -> bool {
+  let transform = &inherited.transform;
   let mut has_scrolled = false;
   // Loop through current touches, is it dragging something?
   for touch in &input_state.fingers {
@@ -73,8 +111,11 @@ pub fn process_scroll(layer: &mut Layer, input_state: &InputState) -> bool {
 
       e_id = touch.curr_dragging.unwrap();
       offset = touch.offset.unwrap();
-      curr_touch = touch.points[touch.latest_point];
-      start_touch = touch.start_point;
+      let inverse = layer.get_parent_transform(e_id, transform).inverse();
+      if inverse.is_none() { continue; }
+      let inverse = inverse.unwrap();
+      curr_touch = transform_point(&inverse, touch.points[touch.latest_point]);
+      start_touch = transform_point(&inverse, touch.start_point);
     }
 
     // Scroll the entity!
@@ -126,9 +167,15 @@ pub fn process_scroll(layer: &mut Layer, input_state: &InputState) -> bool {
     has_scrolled = true;
   }
 
+  if has_scrolled {
+    layer.mark_dirty();
+  }
+
   // Try nested layers
-  for l in &mut layer.component_layer {
-    has_scrolled = if process_scroll(l, input_state) {true} else {has_scrolled};
+  let nested_inherited : Vec<Inherited> = layer.component_layer.into_iter()
+    .map(|l| layer.get_nested_inherited(l, inherited)).collect();
+  for (l, i) in (&mut layer.component_layer).into_iter().zip(nested_inherited.iter()) {
+    has_scrolled = if process_scroll(l, input_state, i) {true} else {has_scrolled};
   }
 
   return has_scrolled;
@@ -13,12 +13,17 @@ mod vlist;
 
 /// Layout a view layer.
 pub fn layout_layer(layer : &mut Layer) {
+  // Parents are looked up during and after layout, so map them once here
+  layer.update_parents();
   // Check that the hierarchy is not malformed
   let tree = entity_tree::EntityTree::new_from_layer(layer);
   if tree.is_none() {
     return;
   }
   let tree = tree.unwrap();
+  // Keep the AABBs from before layout, to check if anything has moved
+  let old_aabbs : Vec<(f32, f32, f32, f32)> = layer.component_aabb.into_iter()
+    .map(|a| (a.x, a.y, a.w, a.h)).collect();
   let roots = tree.get_roots();
   let mut node_queue = Vec::with_capacity(tree.len());
   let mut new_nodes = Vec::new();
@@ -46,6 +51,13 @@ pub fn layout_layer(layer : &mut Layer) {
     new_nodes.clear();
   }
 
+  // If any AABB has changed, the layer needs redrawing
+  let changed = layer.component_aabb.into_iter().zip(old_aabbs.iter())
+    .any(|(a, old)| (a.x, a.y, a.w, a.h) != *old);
+  if changed {
+    layer.mark_dirty();
+  }
+
   // Loop through nested layers, layout all of them
   for l in &mut layer.component_layer {
     if l.entity_id.is_none() { continue; }
@@ -5,13 +5,13 @@ use entity::core::ComponentContainer;
 pub fn layout(layer: &mut Layer, component: &ComponentContainer) {
   match component.layout {
     Layout::VList {ref entity_list, item_height} => {
-      // Find container aabb
+      // Find the area inside the container's padding
       let c_aabb_tuple;
       {
-        let cont_aabb = layer.component_aabb.get_component(component.entity_id);
-        if cont_aabb.is_none() { return; }
-        let cont_aabb = cont_aabb.unwrap();
-        c_aabb_tuple = (cont_aabb.x, cont_aabb.y, cont_aabb.w);
+        let cont_rect = layer.get_content_rect(component.entity_id);
+        if cont_rect.is_none() { return; }
+        let cont_rect = cont_rect.unwrap();
+        c_aabb_tuple = (cont_rect.x, cont_rect.y, cont_rect.w);
       }
       let (c_x, c_y, c_w) = c_aabb_tuple;
 
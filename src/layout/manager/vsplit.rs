@@ -5,19 +5,18 @@ use layout::Layout;
 pub fn layout(layer: &mut Layer, component: &ComponentContainer) {
   match component.layout {
     Layout::VSplit {entity_l, entity_r, split_pos} => {
-      // Find this component's AABB
-      let layer_component_aabb = &mut layer.component_aabb;
+      // Find the area inside this component's padding
       let (c_x, c_y, c_w, c_h);
       {
-        let c_aabb 
-          = layer_component_aabb.get_component(component.entity_id);
-        if c_aabb.is_none() { return; }
-        let c_aabb = c_aabb.unwrap();
-        c_x = c_aabb.x;
-        c_y = c_aabb.y;
-        c_w = c_aabb.w;
-        c_h = c_aabb.h;
+        let c_rect = layer.get_content_rect(component.entity_id);
+        if c_rect.is_none() { return; }
+        let c_rect = c_rect.unwrap();
+        c_x = c_rect.x;
+        c_y = c_rect.y;
+        c_w = c_rect.w;
+        c_h = c_rect.h;
       }
+      let layer_component_aabb = &mut layer.component_aabb;
 
       {
         // Find left side entity AABB
@@ -20,7 +20,7 @@ pub enum Layout {
     entity_header : EntityID,
     /// The EntityID of the body (rest of the page)
     entity_body : EntityID,
-    /// Height of the header in pixels
+    /// Height of the header in dp
     header_height: f32,
   },
   /// Vertical split layout, (as in vim). A line drawn down the split would be
@@ -30,7 +30,7 @@ pub enum Layout {
     entity_l : EntityID,
     /// Entity on the right
     entity_r : EntityID,
-    /// Relative position of the split in pixels. This will be the size of
+    /// Relative position of the split in dp. This will be the size of
     /// entity_l.
     split_pos : f32,
   },
@@ -44,7 +44,7 @@ pub enum Layout {
 
 impl Layout {
   /// Function which will get the children from an enum variant.
-  fn get_children(&self) -> Vec<EntityID> {
+  pub fn get_children(&self) -> Vec<EntityID> {
     match *self {
       // Header bar
       Layout::HeaderBar {entity_header, entity_body, header_height: _} => 
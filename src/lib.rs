@@ -1,6 +1,7 @@
 extern crate android_glue;
 extern crate ffi_glue;
 extern crate libc;
+extern crate png;
 extern crate time;
 #[macro_use]
 extern crate glium;
@@ -25,12 +26,43 @@ pub mod entity;
 /// A module which defines ways in which to layout entities.
 pub mod layout;
 
+/// Image module, contains an RGBA image type used for frame captures, along
+/// with PNG encoding.
+pub mod image;
+
+/// Style module, contains themes of named styles which entities refer to.
+pub mod style;
+
+/// Timer module, defines one-shot timers which wake the run loop.
+pub mod timer;
+
 /// Animation system module. Applies animations.
 mod animation;
 
 /// Input system module. Listens for input and modifies state accordingly.
 mod input;
 
+use std::rc::Rc;
+use std::time::Duration;
+
+/// How long the run loop sleeps between checks for input when idle, in
+/// milliseconds. This is only a polling fallback for platforms without a
+/// platform event receiver, where glutin can't block until input arrives.
+/// Elsewhere the loop blocks until an event arrives or a timer is due.
+const IDLE_POLL_MS: u64 = 10;
+
+/// Colour the screen is cleared to before rendering a view.
+const CLEAR_COLOR: (f32, f32, f32, f32) = (0.1, 0.1, 0.1, 1.0);
+
+/// Trait which defines a 'ResizeListener', a listener who is called when the
+/// window changes size (e.g when the device is rotated).
+pub trait ResizeListener {
+  /// Called once the renderer and root layers have been resized, and the views
+  /// have been laid out again. The new size is in dp, like all other sizes
+  /// given to guitk.
+  fn on_resize(&self, w: f32, h: f32);
+}
+
 /// A struct which contains all the state needed by the library to function -
 /// i.e a reference to the glutin Facade created for event handling and
 /// rendering. 
@@ -39,18 +71,54 @@ pub struct LibState<'a> {
   display: glium::backend::glutin_backend::GlutinFacade,
 
   /// The renderer
-  renderer: Option<renderer::Renderer<'a>>,
+  renderer: Option<renderer::Renderer>,
 
   pub view_stack: Vec<view::View<'a>>,
 
+  /// Number of physical pixels per density independent pixel (dp). All
+  /// positions and sizes given to guitk are in dp.
+  dpi_scale: f32,
+  /// True if dpi_scale was set by the app, rather than read from the device.
+  dpi_scale_overridden: bool,
+
+  /// Listeners notified when the window is resized
+  pub resize_listeners: Vec<&'a ResizeListener>,
+  /// Listeners notified when animations complete or are cancelled
+  pub anim_listeners: Vec<&'a entity::animation::AnimListener>,
+
+  /// Theme applied to entities with a ComponentStyle
+  theme: style::Theme,
+  /// Incremented whenever the theme changes. Views remember the generation
+  /// they were last styled with, so views pushed later are styled too.
+  theme_generation: u64,
+
+  /// Pending timers, in the order they were added
+  timers: Vec<timer::Timer<'a>>,
+  next_timer_id: u32,
+  /// True if the app has asked for a frame to be run, see request_redraw
+  redraw_requested: bool,
+  /// Length of the view stack when the last frame was run, so that frames
+  /// are run when views are pushed or popped
+  last_view_count: usize,
+  /// True once quit has been called, to stop the run loop
+  quit_requested: bool,
+
   /// Input state, used by the input system to track fingers
   input_state: input::InputState,
 
-  /// System time of the last update in nanoseconds. Performance counter time,
+  /// Source of time for frame deltas, animations and timers
+  clock: Rc<timer::Clock>,
+  /// Clock time of the last update in nanoseconds. Performance counter time,
   /// NOT time since UNIX epoch! Don't use for current human time!
   last_update_nanos: u64,
   /// Library update delta in nanoseconds
   frame_delta: u64,
+  /// Speed all animations play at, see set_time_scale
+  time_scale: f64,
+  /// True if the app has paused all animations
+  animations_paused: bool,
+  /// True while the app is paused by the platform, e.g in the background
+  platform_paused: bool,
 }
 
 /// Initialise guitk. Creates an OpenGL context.
@@ -63,9 +131,24 @@ pub fn init<'a>() -> Option<LibState<'a>> {
       .build_glium().unwrap(),
     renderer: None,
     view_stack: Vec::new(),
+    resize_listeners: Vec::new(),
+    anim_listeners: Vec::new(),
+    theme: style::Theme::new(),
+    theme_generation: 0,
+    timers: Vec::new(),
+    next_timer_id: 0,
+    redraw_requested: true,
+    last_view_count: 0,
+    quit_requested: false,
+    dpi_scale: common::units::read_device_scale(),
+    dpi_scale_overridden: false,
     input_state: input::InputState::new(),
+    clock: Rc::new(timer::SystemClock),
     last_update_nanos: time::precise_time_ns(),
     frame_delta: 0,
+    time_scale: 1.0,
+    animations_paused: false,
+    platform_paused: false,
   };
   // Get width / height of window
   {
@@ -83,45 +166,353 @@ pub fn init<'a>() -> Option<LibState<'a>> {
       return None;
     }
     let (w, h) = size_opt.unwrap();
-    lib_state.renderer = Some(renderer::Renderer::new(&lib_state, w, h));
+    let scale = lib_state.dpi_scale;
+    lib_state.renderer = Some(renderer::Renderer::new(&lib_state, w, h, scale));
   }
   return Some(lib_state);
 }
 
 impl<'a> LibState<'a> {
-  /// Update the engine. Call this in your program loop.
+  /// Update the engine. Call this in your program loop. Every call runs a
+  /// whole frame, see run for a loop which only runs frames when needed.
   pub fn update(&mut self) {
     self.update_delta();
     input::process_input(self);
+    self.fire_timers();
+    self.run_frame();
+  }
+
+  /// Runs the engine until quit is called or the view stack is empty. Unlike
+  /// calling update in a loop, frames are only run when input arrives, an
+  /// animation is running, a timer fires, a view is pushed or popped, or
+  /// request_redraw has been called. Otherwise the loop sleeps.
+  pub fn run(&mut self) {
+    self.quit_requested = false;
+    while !self.quit_requested && !self.view_stack.is_empty() {
+      let had_input = input::process_input(self);
+      let timers_fired = self.fire_timers();
+      // A listener or timer may have popped the last view
+      let animating = !self.is_animation_paused() && self.time_scale != 0.0 &&
+        self.view_stack.last().map_or(false, animation::is_animating);
+      let views_changed = self.view_stack.len() != self.last_view_count;
+      if had_input || timers_fired || animating || views_changed ||
+        self.redraw_requested {
+        self.update_delta();
+        self.run_frame();
+        continue;
+      }
+
+      // Nothing to do, wait for input or the next timer. The update time is
+      // moved on, so the first frame after idling has a small delta.
+      let now = self.clock.now_nanos();
+      let mut timeout_nanos = None;
+      for t in &self.timers {
+        let wait = t.deadline_nanos.saturating_sub(now);
+        timeout_nanos = Some(timeout_nanos.map_or(wait, |n: u64| n.min(wait)));
+      }
+      let timeout = timeout_nanos.map(|n| Duration::new(
+        n / 1000000000, (n % 1000000000) as u32));
+      if !input::wait_for_events(self, timeout) {
+        let poll = Duration::from_millis(IDLE_POLL_MS);
+        std::thread::sleep(timeout.map_or(poll, |t| t.min(poll)));
+      }
+      self.last_update_nanos = self.clock.now_nanos();
+    }
+  }
+
+  /// Stops run after the current frame.
+  pub fn quit(&mut self) {
+    self.quit_requested = true;
+  }
+
+  /// Asks for a frame to be run, for when views or components have been
+  /// changed outside of input handling or timers.
+  pub fn request_redraw(&mut self) {
+    self.redraw_requested = true;
+  }
+
+  /// Replaces the clock used for frame deltas, animations and timers. A
+  /// timer::ManualClock makes animations deterministic, e.g for tests.
+  /// Deadlines of pending timers aren't changed, so cancel them first.
+  pub fn set_clock(&mut self, clock: Rc<timer::Clock>) {
+    self.last_update_nanos = clock.now_nanos();
+    self.clock = clock;
+  }
+
+  /// Sets the speed all animations play at, on top of each view's and
+  /// entity's own rate. E.g 0.1 plays animations ten times slower, for
+  /// debugging. A scale of 0 freezes animations like pause_animations does.
+  pub fn set_time_scale(&mut self, scale: f64) {
+    self.time_scale = scale;
+  }
+
+  pub fn get_time_scale(&self) -> f64 {
+    self.time_scale
+  }
+
+  /// Freezes all animations, in every view, where they are.
+  pub fn pause_animations(&mut self) {
+    self.animations_paused = true;
+  }
+
+  /// Continues animations after pause_animations.
+  pub fn resume_animations(&mut self) {
+    self.animations_paused = false;
+  }
+
+  /// Returns true if animations are paused, either by the app or because the
+  /// platform has paused the app.
+  pub fn is_animation_paused(&self) -> bool {
+    self.animations_paused || self.platform_paused
+  }
+
+  /// Adds a one-shot timer which calls the listener after the given delay in
+  /// milliseconds.
+  pub fn add_timer(&mut self, delay_ms: u64,
+                   listener: &'a timer::TimerListener) -> timer::TimerID {
+    let id = timer::TimerID(self.next_timer_id);
+    self.next_timer_id = self.next_timer_id.wrapping_add(1);
+    self.timers.push(timer::Timer {
+      id: id,
+      deadline_nanos: self.clock.now_nanos() + delay_ms * 1000000,
+      listener: listener,
+    });
+    return id;
+  }
+
+  /// Removes a timer before it fires. Does nothing if it has already fired.
+  pub fn cancel_timer(&mut self, id: timer::TimerID) {
+    self.timers.retain(|t| t.id != id);
+  }
+
+  /// Calls the listeners of timers which are due, then removes them. Returns
+  /// true if any fired.
+  fn fire_timers(&mut self) -> bool {
+    let now = self.clock.now_nanos();
+    let mut due = Vec::new();
+    let mut ii = 0;
+    while ii < self.timers.len() {
+      if self.timers[ii].deadline_nanos <= now {
+        due.push(self.timers.remove(ii));
+      }
+      else { ii += 1; }
+    }
+    for t in &due {
+      t.listener.on_timer(self, t.id);
+    }
+    return !due.is_empty();
+  }
+
+  /// Runs animations, styling and layout on the top view, then renders it.
+  fn run_frame(&mut self) {
+    self.redraw_requested = false;
+    self.last_view_count = self.view_stack.len();
+    if self.view_stack.is_empty() { return; }
     animation::process_animations(self);
-    self.view_stack.last_mut().unwrap().layout();
+    let (w, h) = self.get_view_size_dp();
+    {
+      let view = self.view_stack.last_mut().unwrap();
+      if view.get_theme_generation() != Some(self.theme_generation) {
+        style::apply_theme(&self.theme, view);
+        view.set_theme_generation(self.theme_generation);
+      }
+      view.fill_window(w, h);
+      view.layout();
+    }
     self.render();
+    self.dispatch_anim_events();
+  }
+
+  /// Sends the animation events queued by every view to the anim listeners.
+  /// Another frame is run afterwards, so the listeners' changes are drawn.
+  fn dispatch_anim_events(&mut self) {
+    let mut events = Vec::new();
+    for view in &mut self.view_stack {
+      view.drain_anim_events(&mut events);
+    }
+    if events.is_empty() { return; }
+    let listeners = self.anim_listeners.clone();
+    for e in &events {
+      for listener in &listeners {
+        listener.on_anim_event(self, e);
+      }
+    }
+    self.redraw_requested = true;
+  }
+
+  /// Resizes the renderer and every view's root layers to the given window
+  /// size in physical pixels, lays the views out again, then notifies the
+  /// resize listeners. Does nothing if neither the size nor the dpi scale
+  /// has changed.
+  fn resize(&mut self, w: u32, h: u32) {
+    if self.renderer.is_none() { return; }
+    if self.get_view_size() == (w, h) &&
+      self.renderer.as_ref().unwrap().get_scale() == self.dpi_scale { return; }
+    self.renderer.as_mut().unwrap().set_view_size(w, h, self.dpi_scale);
+    let (w_dp, h_dp) = self.get_view_size_dp();
+    for view in &mut self.view_stack {
+      view.fill_window(w_dp, h_dp);
+      view.layout();
+    }
+    for listener in &self.resize_listeners {
+      listener.on_resize(w_dp, h_dp);
+    }
+  }
+
+  /// Queries the current size of the window, or None if the window has been
+  /// closed.
+  fn query_window_size(&self) -> Option<(u32, u32)> {
+    let win_ref = self.display.get_window();
+    if win_ref.is_none() { return None; }
+    win_ref.unwrap().get_inner_size()
   }
 
   /// Update the counter time and delta in LibState.
   fn update_delta(&mut self) {
-    let now = time::precise_time_ns();
-    self.frame_delta = now - self.last_update_nanos;
+    let now = self.clock.now_nanos();
+    self.frame_delta = now.saturating_sub(self.last_update_nanos);
     self.last_update_nanos = now;
   }
 
   /// Renders the view at the top of the view stack
-  fn render(&self) {
-    use glium::Surface;
+  fn render(&mut self) {
     let view = self.view_stack.last();
     if view.is_some() {
       let view = view.unwrap();
+      let renderer = self.renderer.as_mut().unwrap();
+      // Only draw if something changed, otherwise the last frame stays on
+      // screen
+      let damage = renderer.prepare_frame(&self.display, &view.layers);
+      if damage.is_none() { return; }
       let mut target = self.display.draw();
-      target.clear_color(0.1, 0.1, 0.1, 1.0);
+      renderer.render(&self.display, &mut target, &damage.unwrap(), CLEAR_COLOR);
+      let _ = target.finish();
+    }
+  }
+
+  /// Returns an image of what is currently on screen, for bug reports or
+  /// visual assertions in tests. The last rendered frame is read back, rather
+  /// than the view being drawn again. Returns None if no frame has been
+  /// rendered yet, e.g just after the window was resized.
+  pub fn capture_frame(&self) -> Option<image::Image> {
+    if self.renderer.is_none() { return None; }
+    self.renderer.as_ref().unwrap().capture_frame()
+  }
+
+  /// Renders the view at the top of the view stack into an offscreen image,
+  /// cropped to the AABB of the given entity. Returns None if the entity has
+  /// no AABB in any of the view's layers.
+  pub fn capture_entity(&mut self, e_id: entity::EntityID) -> Option<image::Image> {
+    // Search all layers (and nested layers) for the entity's AABB
+    fn find_aabb(layer: &view::Layer,
+                 e_id: entity::EntityID) -> Option<common::rect::Rect> {
+      let aabb = layer.component_aabb.get_component(e_id);
+      if aabb.is_some() {
+        let aabb = aabb.unwrap();
+        return Some(common::rect::Rect::new(aabb.x, aabb.y, aabb.w, aabb.h));
+      }
+      for l in &layer.component_layer {
+        let rect = find_aabb(l, e_id);
+        if rect.is_some() { return rect; }
+      }
+      return None;
+    }
+
+    let mut area = None;
+    if let Some(view) = self.view_stack.last() {
       for layer in &view.layers {
-        self.renderer.as_ref().unwrap().render(self, &mut target, layer, None);
+        area = find_aabb(layer, e_id);
+        if area.is_some() { break; }
       }
-      let _ = target.finish();
     }
+    if area.is_none() { return None; }
+    self.capture_area(&area.unwrap())
+  }
+
+  /// Renders the given area (in dp) of the view at the top of the view stack
+  /// into an image.
+  fn capture_area(&mut self, area: &common::rect::Rect) -> Option<image::Image> {
+    let view = self.view_stack.last();
+    if view.is_none() || self.renderer.is_none() { return None; }
+    self.renderer.as_mut().unwrap().capture(&self.display, &view.unwrap().layers,
+                                            area, CLEAR_COLOR)
+  }
+
+  /// Replaces the theme, e.g to switch between light and dark, and applies it
+  /// to every view in the view stack.
+  pub fn set_theme(&mut self, theme: style::Theme) {
+    self.theme = theme;
+    self.theme_generation += 1;
+    self.apply_theme();
+  }
+
+  pub fn get_theme(&self) -> &style::Theme {
+    &self.theme
+  }
+
+  /// Applies the theme to every view in the view stack again. Views are
+  /// styled when they are first shown, so only call this after adding or
+  /// changing ComponentStyles in a view which is already in the stack.
+  pub fn apply_theme(&mut self) {
+    for view in &mut self.view_stack {
+      style::apply_theme(&self.theme, view);
+      view.set_theme_generation(self.theme_generation);
+    }
+  }
+
+  /// Returns counters describing the work done to render the last frame, or
+  /// the default (all zero) stats if the renderer has not been initialised.
+  pub fn get_frame_stats(&self) -> renderer::FrameStats {
+    if self.renderer.is_some() {
+      self.renderer.as_ref().unwrap().get_frame_stats()
+    }
+    else { renderer::FrameStats::default() }
+  }
+
+  /// Returns the size of the screen in dp, or (0, 0) if the renderer has not
+  /// been initialised.
+  pub fn get_view_size_dp(&self) -> (f32, f32) {
+    let (w, h) = self.get_view_size();
+    (self.px_to_dp(w as f32), self.px_to_dp(h as f32))
+  }
+
+  /// Returns the number of physical pixels per dp.
+  pub fn get_dpi_scale(&self) -> f32 {
+    self.dpi_scale
+  }
+
+  /// Overrides the number of physical pixels per dp read from the device.
+  /// Useful on desktop, where there is no device configuration to read. The
+  /// scale must be positive and finite, other values (including NaN) are
+  /// ignored and the current scale is kept.
+  pub fn set_dpi_scale(&mut self, scale: f32) {
+    if !(scale > 0.0) || !scale.is_finite() { return; }
+    self.dpi_scale = scale;
+    self.dpi_scale_overridden = true;
+    let (w, h) = self.get_view_size();
+    self.resize(w, h);
+  }
+
+  /// Re-reads the dpi scale from the device configuration, unless it has been
+  /// overridden with set_dpi_scale.
+  fn reload_dpi_scale(&mut self) {
+    if !self.dpi_scale_overridden {
+      self.dpi_scale = common::units::read_device_scale();
+    }
+  }
+
+  /// Converts a length in dp to physical pixels.
+  pub fn dp_to_px(&self, dp: f32) -> f32 {
+    dp * self.dpi_scale
+  }
+
+  /// Converts a length in physical pixels to dp.
+  pub fn px_to_dp(&self, px: f32) -> f32 {
+    px / self.dpi_scale
   }
 
-  /// Returns the size of the screen currently, or (0, 0) if the renderer has
-  /// not been initialised.
+  /// Returns the size of the screen in physical pixels currently, or (0, 0)
+  /// if the renderer has not been initialised.
   pub fn get_view_size(&self) -> (u32, u32) {
     if self.renderer.is_some() {
       let (w, h) = self.renderer.as_ref().unwrap().get_view_size();
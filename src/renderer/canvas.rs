@@ -0,0 +1,67 @@
+use common::color::RGBAf32;
+use renderer::Vertex;
+use renderer::path::{self, Path, FillRule, StrokeStyle};
+
+/// Trait for drawing custom content for an entity, see
+/// entity::core::ComponentCustomDraw.
+pub trait CustomPainter {
+  /// Called every time the entity is drawn. The canvas covers the entity's
+  /// AABB, with (0, 0) at its top left.
+  fn paint(&self, canvas: &mut Canvas);
+}
+
+/// Surface that custom painters draw paths onto. Paths are tessellated into
+/// triangles for the renderer. They are not anti-aliased.
+pub struct Canvas<'a> {
+  data: &'a mut Vec<Vertex>,
+  /// Position of the canvas in the view, in dp
+  origin: (f32, f32),
+  size: (f32, f32),
+  /// How far flattened curves may be from the real curve, in dp
+  tolerance: f32,
+}
+
+impl<'a> Canvas<'a> {
+  pub fn new(data: &'a mut Vec<Vertex>, origin: (f32, f32), size: (f32, f32),
+             tolerance: f32) -> Canvas<'a> {
+    Canvas {
+      data: data,
+      origin: origin,
+      size: size,
+      tolerance: tolerance,
+    }
+  }
+
+  /// Returns the width and height of the canvas, in dp.
+  pub fn get_size(&self) -> (f32, f32) {
+    self.size
+  }
+
+  /// Fills a path. Open subpaths are closed for filling.
+  pub fn fill(&mut self, path: &Path, rule: FillRule, color: &RGBAf32) {
+    let polygons : Vec<Vec<(f32, f32)>> = path.flatten(self.tolerance)
+      .into_iter().map(|(points, _)| points).collect();
+    let triangles = path::fill_polygons(&polygons, rule);
+    self.push_triangles(&triangles, color);
+  }
+
+  /// Strokes the outline of a path.
+  pub fn stroke(&mut self, path: &Path, style: &StrokeStyle, color: &RGBAf32) {
+    let mut polygons = Vec::new();
+    for (points, closed) in path.flatten(self.tolerance) {
+      polygons.extend(path::stroke_polygons(&points, closed, style));
+    }
+    let triangles = path::fill_polygons(&polygons, FillRule::NonZero);
+    self.push_triangles(&triangles, color);
+  }
+
+  fn push_triangles(&mut self, triangles: &[(f32, f32)], color: &RGBAf32) {
+    let color = color.to_array();
+    for &(x, y) in triangles {
+      self.data.push(Vertex {
+        position: [self.origin.0 + x, self.origin.1 + y],
+        color: color,
+      });
+    }
+  }
+}
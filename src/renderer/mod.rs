@@ -1,26 +1,393 @@
+use std::rc::Rc;
+use std::mem;
 use glium;
 use glium::Surface;
-use glium::uniforms::{UniformsStorage, EmptyUniforms};
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::{Blend, BlendingFunction, LinearBlendingFactor};
+use glium::texture::{Texture2d, RawImage2d, MipmapsOption};
 
-use view::Layer;
+use view::{Layer, CacheMode, Inherited};
+use common::rect::Rect;
+use common::transform::Affine2;
+use image::Image;
+use image::nine_patch::NinePatch;
+use entity::core::ComponentShadow;
+use self::canvas::Canvas;
 use LibState;
 
-#[derive(Copy, Clone)]
-struct Vertex {
+/// Tessellation of filled and bordered shapes into triangles.
+mod shape;
+
+/// Vertex buffers which are kept and partially updated between frames.
+mod stream;
+
+/// Tessellation of stretched nine-patch images.
+mod nine_patch;
+
+/// Vector paths, and their tessellation into triangles.
+pub mod path;
+
+/// Canvas which custom painters draw paths onto.
+pub mod canvas;
+
+use self::stream::StreamBuffer;
+
+/// Vertex of coloured geometry. Public so that a Canvas can be created over a
+/// list of them, but only built by the renderer.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Vertex {
   position: [f32; 2],
   color: [f32; 4],
 }
 implement_vertex!(Vertex, position, color);
 
-pub struct Renderer<'a> {
+/// Vertex used to draw textures: cached layers and images.
+#[derive(Copy, Clone, PartialEq)]
+struct TexVertex {
+  position: [f32; 2],
+  tex_coords: [f32; 2],
+  opacity: f32,
+}
+implement_vertex!(TexVertex, position, tex_coords, opacity);
+
+/// Counters describing the work done by the renderer in the last frame.
+#[derive(Copy, Clone, Default)]
+pub struct FrameStats {
+  /// Number of frames rendered since the renderer was created.
+  pub frame: u64,
+  /// Number of draw calls issued, including draws into cached layers.
+  pub draw_calls: u32,
+  /// Number of bytes of vertex data uploaded to the GPU.
+  pub uploaded_bytes: usize,
+  /// Number of vertices drawn to the screen.
+  pub vertices: usize,
+  /// Area of the view redrawn in the last frame, in dp. None if nothing
+  /// changed, so the frame was skipped.
+  pub damage: Option<Rect>,
+  /// Number of frames skipped because nothing changed.
+  pub skipped_frames: u64,
+}
+
+/// The offscreen texture of a layer with CacheMode::Cached. Owned by the
+/// layer, but only used by the renderer.
+pub struct LayerCache {
+  texture: Option<Rc<Texture2d>>,
+  /// Area of the view the texture covers in dp, and the dpi scale it was
+  /// drawn at. If either changes, the texture is redrawn.
+  bounds: Option<Rect>,
+  scale: f32,
+}
+
+impl LayerCache {
+  pub fn new() -> LayerCache {
+    LayerCache {
+      texture: None,
+      bounds: None,
+      scale: 0.0,
+    }
+  }
+}
+
+/// Creates an orthographic projection matrix which maps the given area of the
+/// view (in dp, origin top left) to the whole render target.
+fn ortho_proj_mat(area: &Rect) -> [[f32; 4]; 4] {
+  // glOrtho(x, x + w, y + h, y, -1, 1);
+  let (x, y, w, h) = (area.x, area.y, area.w, area.h);
+  [[2.0/w,        0.0,                0.0, -0.0],
+   [0.0,         -2.0/h,              0.0,  0.0],
+   [0.0,          0.0,               -1.0,  0.0],
+   [-(2.0*x + w)/w, (2.0*y + h)/h,    0.0,  1.0]]
+}
+
+/// Blending for geometry. Colour is blended as usual, but alpha is
+/// accumulated so that layers drawn into a transparent texture end up with
+/// premultiplied alpha.
+fn geometry_blend() -> Blend {
+  Blend {
+    color: BlendingFunction::Addition {
+      source: LinearBlendingFactor::SourceAlpha,
+      destination: LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    alpha: BlendingFunction::Addition {
+      source: LinearBlendingFactor::One,
+      destination: LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    constant_value: (0.0, 0.0, 0.0, 0.0),
+  }
+}
+
+/// Blending for compositing premultiplied alpha textures.
+fn composite_blend() -> Blend {
+  Blend {
+    color: BlendingFunction::Addition {
+      source: LinearBlendingFactor::One,
+      destination: LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    alpha: BlendingFunction::Addition {
+      source: LinearBlendingFactor::One,
+      destination: LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    constant_value: (0.0, 0.0, 0.0, 0.0),
+  }
+}
+
+/// A range of vertices drawn with a single draw call.
+struct Batch {
+  start: usize,
+  count: usize,
+  /// Clipping rect to draw this batch with in view coordinates, or None for
+  /// no clipping.
+  rect: Option<Rect>,
+  /// Texture to draw (a cached layer or an image), in which case the
+  /// vertices are in the textured buffer. None for coloured geometry.
+  texture: Option<Rc<Texture2d>>,
+}
+
+/// Geometry and draw calls collected for one render target.
+struct DrawList {
+  colored: StreamBuffer<Vertex>,
+  textured: StreamBuffer<TexVertex>,
+  batches: Vec<Batch>,
+  /// Batches from the last frame, to find what has changed.
+  prev_batches: Vec<Batch>,
+}
+
+impl DrawList {
+  fn new() -> DrawList {
+    DrawList {
+      colored: StreamBuffer::new(),
+      textured: StreamBuffer::new(),
+      batches: Vec::new(),
+      prev_batches: Vec::new(),
+    }
+  }
+
+  fn clear(&mut self) {
+    self.colored.clear();
+    self.textured.clear();
+    mem::swap(&mut self.batches, &mut self.prev_batches);
+    self.batches.clear();
+  }
+
+  /// Returns the area covered by a batch's vertices, clipped to its rect.
+  /// prev selects whether the batch is from the last frame or this one.
+  fn get_batch_bounds(&self, batch: &Batch, prev: bool) -> Option<Rect> {
+    let range = batch.start..batch.start + batch.count;
+    let bounds = if batch.texture.is_none() {
+      let data = if prev { self.colored.prev_data() } else { &self.colored.data[..] };
+      data.get(range).and_then(|v| get_bounds(v.iter().map(|v| v.position)))
+    }
+    else {
+      let data = if prev { self.textured.prev_data() } else { &self.textured.data[..] };
+      data.get(range).and_then(|v| get_bounds(v.iter().map(|v| v.position)))
+    };
+    clip_bounds(bounds, batch.rect)
+  }
+
+  /// Returns the area covered by the triangles of a batch which differ from
+  /// the last frame, both where they were and where they are now. The batch
+  /// must have the same range of vertices as last frame.
+  fn get_changed_bounds(&self, batch: &Batch) -> Option<Rect> {
+    let bounds = if batch.texture.is_none() {
+      changed_triangle_bounds(self.colored.prev_data(), &self.colored.data,
+                              batch.start, batch.count, |v| v.position)
+    }
+    else {
+      changed_triangle_bounds(self.textured.prev_data(), &self.textured.data,
+                              batch.start, batch.count, |v| v.position)
+    };
+    clip_bounds(bounds, batch.rect)
+  }
+
+  /// Records that the coloured vertices from start to the end of the buffer
+  /// should be drawn with the given clipping rect. If the previous batch uses
+  /// the same rect, it is extended rather than adding another draw call.
+  fn push_batch(&mut self, start: usize, rect: Option<Rect>) {
+    let count = self.colored.len() - start;
+    if count == 0 { return; }
+    if let Some(last) = self.batches.last_mut() {
+      if last.texture.is_none() && last.rect == rect &&
+        last.start + last.count == start {
+        last.count += count;
+        return;
+      }
+    }
+    self.batches.push(Batch { start: start, count: count, rect: rect, texture: None });
+  }
+
+  /// Records that the textured vertices from start to the end of the buffer
+  /// should be drawn with the given texture and clipping rect. If the
+  /// previous batch uses the same texture and rect, it is extended instead.
+  fn push_textured(&mut self, texture: Rc<Texture2d>, start: usize,
+                   rect: Option<Rect>) {
+    let count = self.textured.len() - start;
+    if count == 0 { return; }
+    if let Some(last) = self.batches.last_mut() {
+      let same_texture = last.texture.is_some() &&
+        Rc::ptr_eq(last.texture.as_ref().unwrap(), &texture);
+      if same_texture && last.rect == rect && last.start + last.count == start {
+        last.count += count;
+        return;
+      }
+    }
+    self.batches.push(Batch { start: start, count: count, rect: rect,
+                              texture: Some(texture) });
+  }
+
+  /// Adds a textured quad compositing a cached layer's texture over the given
+  /// area, scaled around its centre then offset, then transformed.
+  fn push_composite(&mut self, texture: Rc<Texture2d>, area: &Rect,
+                    rect: Option<Rect>, opacity: f32,
+                    offset: (f32, f32), scale: f32, transform: &Affine2) {
+    let (cx, cy) = (area.x + area.w / 2.0 + offset.0, area.y + area.h / 2.0 + offset.1);
+    let (hw, hh) = (area.w * scale / 2.0, area.h * scale / 2.0);
+    let (x0, y0, x1, y1) = (cx - hw, cy - hh, cx + hw, cy + hh);
+    let start = self.textured.len();
+    // The texture is drawn with its origin at the bottom left
+    let quad = [([x0, y0], [0.0, 1.0]), ([x1, y0], [1.0, 1.0]), ([x1, y1], [1.0, 0.0]),
+                ([x0, y0], [0.0, 1.0]), ([x1, y1], [1.0, 0.0]), ([x0, y1], [0.0, 0.0])];
+    for &(position, tex_coords) in &quad {
+      let (x, y) = transform.apply(position[0], position[1]);
+      self.textured.data.push(TexVertex {
+        position: [x, y], tex_coords: tex_coords, opacity: opacity });
+    }
+    self.push_textured(texture, start, rect);
+  }
+
+  /// Uploads both vertex buffers, returning the number of bytes uploaded.
+  fn upload(&mut self, display: &GlutinFacade) -> usize {
+    self.colored.upload(display) + self.textured.upload(display)
+  }
+}
+
+/// Returns the bounding box of a list of positions, or None if it is empty.
+fn get_bounds<I: Iterator<Item=[f32; 2]>>(positions: I) -> Option<Rect> {
+  let mut bounds : Option<Rect> = None;
+  for p in positions {
+    let r = Rect::new(p[0], p[1], 0.0, 0.0);
+    bounds = Some(bounds.map_or(r, |b| b.union(&r)));
+  }
+  return bounds;
+}
+
+/// Clips bounds to a batch's clip rect, if it has one.
+fn clip_bounds(bounds: Option<Rect>, rect: Option<Rect>) -> Option<Rect> {
+  match (bounds, rect) {
+    (Some(b), Some(r)) => Some(b.intersect(&r)).filter(|b| !b.is_empty()),
+    (b, _) => b,
+  }
+}
+
+/// Adds a rect to a damaged area.
+fn add_damage(damage: Option<Rect>, rect: Option<Rect>) -> Option<Rect> {
+  match (damage, rect) {
+    (Some(d), Some(r)) => Some(d.union(&r)),
+    (d, r) => d.or(r),
+  }
+}
+
+/// Compares a range of vertices with the last frame's triangle by triangle,
+/// returning the bounds of every triangle which changed, before and after.
+fn changed_triangle_bounds<T: PartialEq, F: Fn(&T) -> [f32; 2]>(
+  old: &[T], new: &[T], start: usize, count: usize, position: F) -> Option<Rect> {
+  let mut bounds = None;
+  let mut ii = start;
+  while ii + 3 <= start + count && ii + 3 <= new.len() {
+    let old_tri = old.get(ii..ii + 3);
+    let new_tri = &new[ii..ii + 3];
+    if old_tri != Some(new_tri) {
+      bounds = add_damage(bounds, get_bounds(new_tri.iter().map(&position)));
+      if old_tri.is_some() {
+        bounds = add_damage(bounds, get_bounds(old_tri.unwrap().iter().map(&position)));
+      }
+    }
+    ii += 3;
+  }
+  return bounds;
+}
+
+/// Applies a transform to the positions of a run of vertices, and multiplies
+/// their alpha by opacity.
+fn transform_vertices(data: &mut [Vertex], transform: &Affine2, opacity: f32) {
+  if transform.is_identity() && opacity == 1.0 { return; }
+  for v in data {
+    let (x, y) = transform.apply(v.position[0], v.position[1]);
+    v.position = [x, y];
+    v.color[3] *= opacity;
+  }
+}
+
+/// Returns a nine-patch's texture, creating it the first time it is drawn.
+/// The texture is premultiplied, like the textures of cached layers, so that
+/// it can be drawn with the same program.
+fn get_nine_patch_texture(display: &GlutinFacade, nine_patch: &NinePatch)
+                          -> Rc<Texture2d> {
+  let mut cache = nine_patch.get_texture_cache().borrow_mut();
+  if cache.is_none() {
+    let image = &nine_patch.image;
+    let mut data = image.data.clone();
+    for p in data.chunks_mut(4) {
+      let a = p[3] as u32;
+      for c in &mut p[0..3] {
+        *c = ((*c as u32 * a + 127) / 255) as u8;
+      }
+    }
+    let raw = RawImage2d::from_raw_rgba(data, (image.width, image.height));
+    // No mipmaps, so that the parts of the image don't bleed into each other
+    *cache = Some(Rc::new(Texture2d::with_mipmaps(
+      display, raw, MipmapsOption::NoMipmap).unwrap()));
+  }
+  cache.as_ref().unwrap().clone()
+}
+
+/// Reads a texture back into an image. Rows are read from the bottom up,
+/// images are stored top down.
+fn read_texture(texture: &Texture2d) -> Image {
+  let (w, h) = texture.dimensions();
+  let rows : Vec<Vec<(u8, u8, u8, u8)>> = texture.read();
+  let mut data = Vec::with_capacity((w * h * 4) as usize);
+  for row in rows.iter().rev() {
+    for &(r, g, b, a) in row {
+      data.push(r);
+      data.push(g);
+      data.push(b);
+      data.push(a);
+    }
+  }
+  Image::new(w, h, data)
+}
+
+pub struct Renderer {
   program: glium::Program,
-  uniforms: UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>,
+  /// Program used to composite cached layers' textures
+  texture_program: glium::Program,
+  /// Size of the view in physical pixels
   view_w: u32,
   view_h: u32,
+  /// Number of physical pixels per density independent pixel (dp). Everything
+  /// given to the renderer is in dp, and scaled by the projection.
+  scale: f32,
+
+  /// Draw list for the screen, kept between frames so its vertex buffers can
+  /// be reused.
+  screen: DrawList,
+  /// Copy of the last frame. Only the damaged area of it is redrawn each
+  /// frame, then it is drawn to the screen, as the contents of the screen's
+  /// back buffer aren't kept between frames.
+  frame: Option<Texture2d>,
+  /// Quad used to draw the frame texture to the screen.
+  frame_quad: StreamBuffer<TexVertex>,
+  /// Cached layer textures redrawn this frame, which are damaged wherever
+  /// they are composited.
+  redrawn: Vec<Rc<Texture2d>>,
+  /// True while capture is drawing. Cached layers it redraws are left dirty
+  /// and aren't counted as damage, so the next frame still draws them.
+  capturing: bool,
+  stats: FrameStats,
 }
 
-impl<'a> Renderer<'a> {
-  pub fn new(lib_state: &LibState, w: u32, h: u32) -> Renderer<'a> {
+impl Renderer {
+  pub fn new(lib_state: &LibState, w: u32, h: u32, scale: f32) -> Renderer {
     // Vertex shader
     let vert_src = r#"
       #version 100
@@ -49,30 +416,309 @@ impl<'a> Renderer<'a> {
       }
     "#;
 
-    let uniforms = UniformsStorage::new(
-      "proj_mat",
-      // Orthographic proj mat:
-      // glOrtho(0, w, h, 0, -1, 1);
-      [[2.0/w as f32, 0.0,           0.0, -0.0],
-       [0.0,         -2.0/h as f32,  0.0,  0.0],
-       [0.0,          0.0,          -1.0,  0.0],
-       [-1.0,          1.0,           0.0,  1.0]]);
-
-    Renderer { 
-      uniforms: uniforms,
+    // Textured vertex shader, for compositing cached layers
+    let tex_vert_src = r#"
+      #version 100
+      attribute vec2 position;
+      attribute vec2 tex_coords;
+      attribute float opacity;
+
+      varying vec2 v_tex_coords;
+      varying float v_opacity;
+
+      uniform mat4 proj_mat;
+
+      void main() {
+          v_tex_coords = tex_coords;
+          v_opacity = opacity;
+          gl_Position = proj_mat * vec4(position, 0.0, 1.0);
+      }
+    "#;
+
+    // Textured fragment shader. Textures have premultiplied alpha, so the
+    // whole colour is scaled by the opacity.
+    let tex_frag_src = r#"
+      #version 100
+      precision mediump float; // Float precision to medium
+
+      varying vec2 v_tex_coords;
+      varying float v_opacity;
+
+      uniform sampler2D tex;
+
+      void main() {
+        gl_FragColor = texture2D(tex, v_tex_coords) * v_opacity;
+      }
+    "#;
+
+    Renderer {
       view_w: w, view_h: h,
+      scale: scale,
+      screen: DrawList::new(),
+      frame: None,
+      frame_quad: StreamBuffer::new(),
+      redrawn: Vec::new(),
+      capturing: false,
+      stats: FrameStats::default(),
       program: glium::Program::from_source(&lib_state.display,
-                                           vert_src, 
-                                           frag_src, 
+                                           vert_src,
+                                           frag_src,
                                            None).unwrap(),
+      texture_program: glium::Program::from_source(&lib_state.display,
+                                                   tex_vert_src,
+                                                   tex_frag_src,
+                                                   None).unwrap(),
+    }
+  }
+
+  /// Collects the geometry of a list of layers into vertex buffers which are
+  /// kept between frames, uploading only the vertices which changed since the
+  /// last frame. Consecutive layers with the same clipping rect are batched
+  /// together.
+  /// # Returns
+  /// The area of the view (in dp) which differs from the last frame, found by
+  /// comparing the new geometry with the last frame's. None if nothing has
+  /// changed, in which case there is no need to call render.
+  pub fn prepare_frame(&mut self, display: &GlutinFacade,
+                       layers: &[Layer]) -> Option<Rect> {
+    self.stats.draw_calls = 0;
+    self.stats.uploaded_bytes = 0;
+
+    let mut list = mem::replace(&mut self.screen, DrawList::new());
+    list.clear();
+    for layer in layers {
+      self.collect_layer(display, &mut list, layer, None, None,
+                         &Inherited::root(), false);
+    }
+    self.stats.uploaded_bytes += list.upload(display);
+    self.stats.vertices = list.colored.len() + list.textured.len();
+
+    let view_rect = self.get_view_rect();
+    let frame_valid = self.frame.is_some() &&
+      self.frame.as_ref().unwrap().dimensions() == (self.view_w, self.view_h);
+    let damage = if frame_valid { self.get_damage(&list) } else { Some(view_rect) };
+    let damage = damage.map(|d| d.intersect(&view_rect)).filter(|d| !d.is_empty());
+    // Only needed to find this frame's damage
+    self.redrawn.clear();
+    self.screen = list;
+    self.stats.damage = damage;
+    if damage.is_none() {
+      self.stats.skipped_frames += 1;
     }
+    return damage;
   }
 
-  pub fn render(&self, lib_state: &LibState, target: &mut glium::Frame, 
-                layer : &Layer, scissor_rect: Option<glium::Rect>) {
-    // Create VBO data inside vec
-    let mut data = Vec::<Vertex>::with_capacity(
-      layer.component_debug_draw.len()*6);
+  /// Finds the area of the view covered by batches which changed since the
+  /// last frame, or None if nothing changed.
+  fn get_damage(&self, list: &DrawList) -> Option<Rect> {
+    let mut damage = None;
+    for ii in 0..list.batches.len().max(list.prev_batches.len()) {
+      let (old, new) = (list.prev_batches.get(ii), list.batches.get(ii));
+      let same_batch = old.is_some() && new.is_some() && {
+        let (old, new) = (old.unwrap(), new.unwrap());
+        let same_texture = match (&old.texture, &new.texture) {
+          (&Some(ref a), &Some(ref b)) => Rc::ptr_eq(a, b) &&
+            !self.redrawn.iter().any(|t| Rc::ptr_eq(t, b)),
+          (&None, &None) => true,
+          _ => false,
+        };
+        same_texture && old.start == new.start && old.count == new.count &&
+          old.rect == new.rect
+      };
+      if same_batch {
+        damage = add_damage(damage, list.get_changed_bounds(new.unwrap()));
+        continue;
+      }
+      // The batch has changed completely, so where it was and where it is
+      // now are both damaged
+      if old.is_some() {
+        damage = add_damage(damage, list.get_batch_bounds(old.unwrap(), true));
+      }
+      if new.is_some() {
+        damage = add_damage(damage, list.get_batch_bounds(new.unwrap(), false));
+      }
+    }
+    return damage;
+  }
+
+  /// Redraws the damaged area of the frame prepared by prepare_frame, then
+  /// draws the whole frame to the target.
+  pub fn render(&mut self, display: &GlutinFacade, target: &mut glium::Frame,
+                damage: &Rect, clear_color: (f32, f32, f32, f32)) {
+    let (view_w, view_h) = (self.view_w, self.view_h);
+    let area = self.get_view_rect();
+    let frame_valid = self.frame.is_some() &&
+      self.frame.as_ref().unwrap().dimensions() == (view_w, view_h);
+    if !frame_valid {
+      self.frame = Some(Texture2d::empty(display, view_w, view_h).unwrap());
+    }
+
+    {
+      let frame = self.frame.as_ref().unwrap();
+      let mut surface = frame.as_surface();
+      let scissor = self.to_scissor_rect(damage, &area, view_w, view_h);
+      surface.clear(Some(&scissor), Some(clear_color), false, None, None);
+      let draw_calls = self.draw_list(&mut surface, &self.screen, &area,
+                                      view_w, view_h, Some(*damage));
+      self.stats.draw_calls += draw_calls;
+    }
+
+    // Copy the frame to the screen
+    self.frame_quad.clear();
+    let (x1, y1) = (area.w, area.h);
+    let quad = [([0.0, 0.0], [0.0, 1.0]), ([x1, 0.0], [1.0, 1.0]), ([x1, y1], [1.0, 0.0]),
+                ([0.0, 0.0], [0.0, 1.0]), ([x1, y1], [1.0, 0.0]), ([0.0, y1], [0.0, 0.0])];
+    for &(position, tex_coords) in &quad {
+      self.frame_quad.data.push(TexVertex {
+        position: position, tex_coords: tex_coords, opacity: 1.0 });
+    }
+    self.stats.uploaded_bytes += self.frame_quad.upload(display);
+    let vertices = self.frame_quad.slice(0, quad.len()).unwrap();
+    let indices = glium::index::NoIndices(
+      glium::index::PrimitiveType::TrianglesList);
+    let tex : &Texture2d = self.frame.as_ref().unwrap();
+    target.draw(vertices, &indices, &self.texture_program,
+                &uniform! { proj_mat: ortho_proj_mat(&area), tex: tex },
+                &Default::default()).unwrap();
+    self.stats.draw_calls += 1;
+    self.stats.frame += 1;
+  }
+
+  /// Reads the last rendered frame back into an image, exactly as it was
+  /// drawn to the screen. The image is in physical pixels. Returns None if
+  /// no frame has been rendered since the renderer was created or
+  /// invalidated.
+  pub fn capture_frame(&self) -> Option<Image> {
+    let frame_valid = self.frame.is_some() &&
+      self.frame.as_ref().unwrap().dimensions() == (self.view_w, self.view_h);
+    if !frame_valid { return None; }
+    Some(read_texture(self.frame.as_ref().unwrap()))
+  }
+
+  /// Renders a list of layers into an offscreen texture covering the given
+  /// area of the view (in dp), then reads it back into an image. The image
+  /// is in physical pixels. Returns None if the area is empty. Cached layers
+  /// redrawn for the capture stay dirty, so what is on screen isn't affected.
+  pub fn capture(&mut self, display: &GlutinFacade, layers: &[Layer], area: &Rect,
+                 clear_color: (f32, f32, f32, f32)) -> Option<Image> {
+    let w = (area.w * self.scale).ceil() as u32;
+    let h = (area.h * self.scale).ceil() as u32;
+    if w == 0 || h == 0 { return None; }
+
+    let mut list = DrawList::new();
+    self.capturing = true;
+    for layer in layers {
+      self.collect_layer(display, &mut list, layer, None, None,
+                         &Inherited::root(), false);
+    }
+    self.capturing = false;
+    list.upload(display);
+    let texture = Texture2d::empty(display, w, h).unwrap();
+    {
+      let mut surface = texture.as_surface();
+      let (r, g, b, a) = clear_color;
+      surface.clear_color(r, g, b, a);
+      self.draw_list(&mut surface, &list, area, w, h, None);
+    }
+    Some(read_texture(&texture))
+  }
+
+  /// Appends the vertices of a layer and its nested layers to a draw list,
+  /// and records which clipping rect they should be drawn with. If the layer
+  /// is cached, its texture is brought up to date and composited instead.
+  /// # Arguments
+  /// - clip_rect The rect this layer is clipped to, or None for root layers
+  /// - outer_clip_rect The rect the layer this is nested in is clipped to.
+  ///   Cached layers are composited with this, so that their composite
+  ///   transform can move them outside of their own clip rect.
+  /// - inherited The transform and opacity inherited from the entity this
+  ///   layer belongs to, and its parents
+  /// - cache_root True if the layer is being drawn into its own cache
+  fn collect_layer(&mut self, display: &GlutinFacade, list: &mut DrawList,
+                   layer: &Layer, clip_rect: Option<Rect>,
+                   outer_clip_rect: Option<Rect>, inherited: &Inherited,
+                   cache_root: bool) {
+    if inherited.opacity <= 0.0 { return; }
+    let transform = &inherited.transform;
+    if layer.cache_mode == CacheMode::Cached && !cache_root {
+      // The cache is drawn untransformed and opaque, covering the part of the
+      // layer which is visible once transformed. The transform and opacity are
+      // applied when compositing.
+      let area = clip_rect.unwrap_or(self.get_view_rect());
+      let inverse = transform.inverse();
+      if inverse.is_none() { return; }
+      let area = inverse.unwrap().transform_rect(&area);
+      self.update_layer_cache(display, layer, &area);
+      let texture = layer.get_cache().borrow().texture.clone();
+      if texture.is_some() {
+        list.push_composite(texture.unwrap(), &area, outer_clip_rect,
+                            layer.composite_opacity * inherited.opacity,
+                            layer.composite_offset, layer.composite_scale,
+                            transform);
+      }
+      return;
+    }
+
+    let start = list.colored.len();
+    // Shadows of entities without a background go beneath every background
+    for sh in &layer.component_shadow {
+      if layer.component_background.get_component(sh.entity_id).is_some() { continue; }
+      self.push_shadow(list, layer, sh, inherited);
+    }
+    // Then backgrounds, so that everything else is drawn on top. Each
+    // entity's shadow is drawn just before its background, so it falls on the
+    // backgrounds of the entities beneath it
+    for bg in &layer.component_background {
+      let aabb = layer.component_aabb.get_component(bg.entity_id);
+      if aabb.is_none() { continue; }
+      let opacity = layer.get_entity_opacity(bg.entity_id, inherited.opacity);
+      let t = layer.get_entity_transform(bg.entity_id, transform);
+      let scale_factor = t.get_scale_factor();
+      if opacity <= 0.0 || scale_factor <= 0.0 { continue; }
+      let sh = layer.component_shadow.get_component(bg.entity_id);
+      if sh.is_some() {
+        self.push_shadow(list, layer, sh.unwrap(), inherited);
+      }
+      let first = list.colored.len();
+      shape::push_background(&mut list.colored.data, aabb.unwrap(), bg,
+                             1.0 / (self.scale * scale_factor));
+      transform_vertices(&mut list.colored.data[first..], &t, opacity);
+    }
+    list.push_batch(start, clip_rect);
+    // Then nine-patches, which are drawn over backgrounds
+    for np in &layer.component_nine_patch {
+      let aabb = layer.component_aabb.get_component(np.entity_id);
+      if aabb.is_none() { continue; }
+      let opacity = layer.get_entity_opacity(np.entity_id, inherited.opacity);
+      if opacity <= 0.0 { continue; }
+      let t = layer.get_entity_transform(np.entity_id, transform);
+      let texture = get_nine_patch_texture(display, &np.nine_patch);
+      let start = list.textured.len();
+      nine_patch::push_nine_patch(&mut list.textured.data, aabb.unwrap(),
+                                  &np.nine_patch, &t, opacity);
+      list.push_textured(texture, start, clip_rect);
+    }
+
+    let start = list.colored.len();
+    // Custom drawn content
+    for cd in &layer.component_custom_draw {
+      let aabb = layer.component_aabb.get_component(cd.entity_id);
+      if aabb.is_none() { continue; }
+      let aabb = aabb.unwrap();
+      let opacity = layer.get_entity_opacity(cd.entity_id, inherited.opacity);
+      let t = layer.get_entity_transform(cd.entity_id, transform);
+      let scale_factor = t.get_scale_factor();
+      if opacity <= 0.0 || scale_factor <= 0.0 { continue; }
+      let first = list.colored.len();
+      {
+        // Flatten curves to within a quarter of a physical pixel
+        let mut canvas = Canvas::new(&mut list.colored.data, (aabb.x, aabb.y),
+                                     (aabb.w, aabb.h),
+                                     0.25 / (self.scale * scale_factor));
+        cd.painter.paint(&mut canvas);
+      }
+      transform_vertices(&mut list.colored.data[first..], &t, opacity);
+    }
     // Loop through debug draw components, find matching AABB component, then
     // draw
     for dd in &layer.component_debug_draw {
@@ -81,67 +727,191 @@ impl<'a> Renderer<'a> {
       // Found a matching AABB component, we can draw!
       let aabb = aabb.unwrap();
       let (ax, ay, aw, ah) = (aabb.x, aabb.y, aabb.w, aabb.h);
+      let color = [dd.color.r, dd.color.g, dd.color.b, 0.5];
 
-      data.push(Vertex{
-        position: [ax, ay], 
-        color: [dd.color.r, dd.color.g, dd.color.b, 0.5]});
-      data.push(Vertex{
-        position: [ax+aw, ay], 
-        color: [dd.color.r, dd.color.g, dd.color.b, 0.5]});
-      data.push(Vertex{
-        position: [ax+aw, ay+ah], 
-        color: [dd.color.r, dd.color.g, dd.color.b, 0.5]});
-      data.push(Vertex{
-        position: [ax, ay], 
-        color: [dd.color.r, dd.color.g, dd.color.b, 0.5]});
-      data.push(Vertex{
-        position: [ax, ay+ah], 
-        color: [dd.color.r, dd.color.g, dd.color.b, 0.5]});
-      data.push(Vertex{
-        position: [ax+aw, ay+ah], 
-        color: [dd.color.r, dd.color.g, dd.color.b, 0.5]});
-    }
-
-    let vbo = glium::VertexBuffer::new(&lib_state.display, &data).unwrap();
-    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
-
-    // Apply scissor to draw params
-    let mut draw_params = glium::draw_parameters::DrawParameters::default();
-    draw_params.viewport = scissor_rect;
-    //draw_params.scissor = scissor_rect;
-    draw_params.blend = glium::draw_parameters::Blend::alpha_blending();
-
-    target.draw(&vbo, 
-                indices, &self.program,
-                &self.uniforms,
-                &draw_params).unwrap();
-
-    // Find nested layers and render them
+      let data = &mut list.colored.data;
+      let first = data.len();
+      data.push(Vertex{ position: [ax, ay], color: color });
+      data.push(Vertex{ position: [ax+aw, ay], color: color });
+      data.push(Vertex{ position: [ax+aw, ay+ah], color: color });
+      data.push(Vertex{ position: [ax, ay], color: color });
+      data.push(Vertex{ position: [ax, ay+ah], color: color });
+      data.push(Vertex{ position: [ax+aw, ay+ah], color: color });
+      let opacity = layer.get_entity_opacity(dd.entity_id, inherited.opacity);
+      let t = layer.get_entity_transform(dd.entity_id, transform);
+      transform_vertices(&mut data[first..], &t, opacity);
+    }
+    list.push_batch(start, clip_rect);
+
+    // Find nested layers and collect them, clipped to the intersection of
+    // their transformed AABB and this layer's clip rect. Scissor rects can't
+    // be rotated, so rotated layers are clipped to their bounding box.
     for l in &layer.component_layer {
-      if l.entity_id.is_none() { continue; }
-      let aabb = layer.component_aabb.get_component(l.entity_id.unwrap());
-      if aabb.is_none() { continue; }
-      let aabb = aabb.unwrap();
-      let rect;
-      if scissor_rect.is_none() {
-        rect = glium::Rect{left: 0, bottom: 0, width: self.view_w, height: self.view_h};
+      let rect = layer.get_nested_clip_rect(l);
+      if rect.is_none() { continue; }
+      let nested_inherited = layer.get_nested_inherited(l, inherited);
+      let mut rect = nested_inherited.transform.transform_rect(&rect.unwrap());
+      if clip_rect.is_some() {
+        rect = rect.intersect(&clip_rect.unwrap());
+      }
+      if rect.is_empty() { continue; }
+      self.collect_layer(display, list, l, Some(rect), clip_rect,
+                         &nested_inherited, false);
+    }
+  }
+
+  /// Appends the vertices of an entity's shadow to a draw list, following the
+  /// rounded corners of its background if it has one.
+  fn push_shadow(&self, list: &mut DrawList, layer: &Layer, sh: &ComponentShadow,
+                 inherited: &Inherited) {
+    let aabb = layer.component_aabb.get_component(sh.entity_id);
+    if aabb.is_none() { return; }
+    let opacity = layer.get_entity_opacity(sh.entity_id, inherited.opacity);
+    let t = layer.get_entity_transform(sh.entity_id, &inherited.transform);
+    let scale_factor = t.get_scale_factor();
+    if opacity <= 0.0 || scale_factor <= 0.0 { return; }
+    let radii = layer.component_background.get_component(sh.entity_id)
+      .map_or([0.0; 4], |bg| bg.corner_radii);
+    let first = list.colored.len();
+    shape::push_shadow(&mut list.colored.data, aabb.unwrap(), sh, radii,
+                       1.0 / (self.scale * scale_factor));
+    transform_vertices(&mut list.colored.data[first..], &t, opacity);
+  }
+
+  /// Redraws a cached layer into its texture if it has been marked dirty, or
+  /// the area it covers has changed since it was last drawn.
+  fn update_layer_cache(&mut self, display: &GlutinFacade, layer: &Layer,
+                        area: &Rect) {
+    let w = (area.w * self.scale).ceil() as u32;
+    let h = (area.h * self.scale).ceil() as u32;
+    if w == 0 || h == 0 { return; }
+    {
+      let mut cache = layer.get_cache().borrow_mut();
+      let up_to_date = cache.texture.is_some() && !layer.is_dirty() &&
+        cache.bounds == Some(*area) && cache.scale == self.scale;
+      if up_to_date { return; }
+      let size_matches = cache.texture.is_some() &&
+        cache.texture.as_ref().unwrap().dimensions() == (w, h);
+      if !size_matches {
+        cache.texture = Some(Rc::new(Texture2d::empty(display, w, h).unwrap()));
+      }
+      cache.bounds = Some(*area);
+      cache.scale = self.scale;
+    }
+
+    let mut list = DrawList::new();
+    self.collect_layer(display, &mut list, layer, Some(*area), None,
+                       &Inherited::root(), true);
+    self.stats.uploaded_bytes += list.upload(display);
+    let texture = layer.get_cache().borrow().texture.clone().unwrap();
+    let mut surface = texture.as_surface();
+    surface.clear_color(0.0, 0.0, 0.0, 0.0);
+    self.stats.draw_calls += self.draw_list(&mut surface, &list, area, w, h, None);
+    if self.capturing { return; }
+    self.redrawn.push(texture.clone());
+    layer.clear_dirty();
+  }
+
+  /// Issues the draw calls in a draw list.
+  /// # Arguments
+  /// - area The area of the view the surface covers, in dp
+  /// - surface_w, surface_h The size of the surface in physical pixels
+  /// - damage If given, only this area of the view is redrawn
+  /// # Returns
+  /// The number of draw calls issued.
+  fn draw_list<S: Surface>(&self, surface: &mut S, list: &DrawList, area: &Rect,
+                           surface_w: u32, surface_h: u32,
+                           damage: Option<Rect>) -> u32 {
+    let proj_mat = ortho_proj_mat(area);
+    let indices = glium::index::NoIndices(
+      glium::index::PrimitiveType::TrianglesList);
+    let mut draw_calls = 0;
+    for batch in &list.batches {
+      // Apply scissor to draw params, skipping batches outside the damage
+      let rect = match (batch.rect, damage) {
+        (Some(r), Some(d)) => Some(r.intersect(&d)),
+        (r, d) => r.or(d),
+      };
+      if rect.is_some() && rect.unwrap().is_empty() { continue; }
+      let mut draw_params = glium::draw_parameters::DrawParameters::default();
+      draw_params.scissor = rect.map(
+        |r| self.to_scissor_rect(&r, area, surface_w, surface_h));
+
+      if batch.texture.is_none() {
+        let vertices = list.colored.slice(batch.start, batch.count);
+        if vertices.is_none() { continue; }
+        draw_params.blend = geometry_blend();
+        surface.draw(vertices.unwrap(), &indices, &self.program,
+                     &uniform! { proj_mat: proj_mat },
+                     &draw_params).unwrap();
       }
       else {
-        rect = scissor_rect.unwrap();
+        let vertices = list.textured.slice(batch.start, batch.count);
+        if vertices.is_none() { continue; }
+        let texture : &Texture2d = batch.texture.as_ref().unwrap();
+        draw_params.blend = composite_blend();
+        surface.draw(vertices.unwrap(), &indices, &self.texture_program,
+                     &uniform! { proj_mat: proj_mat, tex: texture },
+                     &draw_params).unwrap();
       }
-      let w_scale = rect.width as f32 / self.view_w as f32;
-      let h_scale = rect.height as f32 / self.view_h as f32;
-      // Render nested layer with the correct scissor params
-      self.render(lib_state, target, l, Some(glium::Rect {
-        left: (aabb.x * w_scale) as u32 + rect.left,
-        bottom: rect.bottom + (rect.height as f32 - (aabb.y + aabb.h) * h_scale) as u32,
-        width: (aabb.w * w_scale) as u32,
-        height: (aabb.h * h_scale) as u32,
-      }));
+      draw_calls += 1;
+    }
+    return draw_calls;
+  }
+
+  /// Converts a clip rect in view coordinates (dp, origin top left) to a GL
+  /// scissor rect (physical pixels, origin bottom left) on a surface covering
+  /// the given area of the view, rounding outwards to whole pixels.
+  fn to_scissor_rect(&self, rect: &Rect, area: &Rect,
+                     surface_w: u32, surface_h: u32) -> glium::Rect {
+    let (surface_w, surface_h) = (surface_w as f32, surface_h as f32);
+    let s = self.scale;
+    let (x, y) = (rect.x - area.x, rect.y - area.y);
+    let x0 = (x * s).floor().max(0.0).min(surface_w);
+    let y0 = (y * s).floor().max(0.0).min(surface_h);
+    let x1 = ((x + rect.w) * s).ceil().max(x0).min(surface_w);
+    let y1 = ((y + rect.h) * s).ceil().max(y0).min(surface_h);
+    glium::Rect {
+      left: x0 as u32,
+      bottom: (surface_h - y1) as u32,
+      width: (x1 - x0) as u32,
+      height: (y1 - y0) as u32,
     }
   }
 
+  /// Returns the whole view as a rect in dp.
+  fn get_view_rect(&self) -> Rect {
+    Rect::new(0.0, 0.0, self.view_w as f32 / self.scale,
+              self.view_h as f32 / self.scale)
+  }
+
+  /// Returns counters describing the last rendered frame.
+  pub fn get_frame_stats(&self) -> FrameStats {
+    self.stats
+  }
+
+  /// Changes the size of the view in physical pixels, and the number of
+  /// physical pixels per dp.
+  pub fn set_view_size(&mut self, w: u32, h: u32, scale: f32) {
+    self.view_w = w;
+    self.view_h = h;
+    self.scale = scale;
+    self.invalidate();
+  }
+
+  /// Forces the next frame to be redrawn completely, e.g when the window's
+  /// contents have been lost.
+  pub fn invalidate(&mut self) {
+    self.frame = None;
+  }
+
+  /// Returns the size of the view in physical pixels.
   pub fn get_view_size(&self) -> (u32, u32) {
     (self.view_w, self.view_h)
   }
+
+  /// Returns the number of physical pixels per dp.
+  pub fn get_scale(&self) -> f32 {
+    self.scale
+  }
 }
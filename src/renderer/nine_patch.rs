@@ -0,0 +1,51 @@
+use common::transform::Affine2;
+use entity::core::ComponentAABB;
+use image::nine_patch::NinePatch;
+use renderer::TexVertex;
+
+/// Pushes triangles for a nine-patch stretched over the given AABB. The
+/// unstretched edges are scaled down if the AABB is too small to fit them.
+pub fn push_nine_patch(data: &mut Vec<TexVertex>, aabb: &ComponentAABB,
+                       nine_patch: &NinePatch, transform: &Affine2, opacity: f32) {
+  let (x, y, w, h) = (aabb.x, aabb.y, aabb.w, aabb.h);
+  let (iw, ih) = (nine_patch.image.width as f32, nine_patch.image.height as f32);
+  if w <= 0.0 || h <= 0.0 || iw <= 0.0 || ih <= 0.0 { return; }
+  let insets = nine_patch.insets;
+
+  // Edge sizes in dp
+  let edges = insets.scale(1.0 / nine_patch.density);
+  let (mut l, mut r) = (edges.left, edges.right);
+  let (mut t, mut b) = (edges.top, edges.bottom);
+  if l + r > w {
+    let s = w / (l + r);
+    l *= s;
+    r *= s;
+  }
+  if t + b > h {
+    let s = h / (t + b);
+    t *= s;
+    b *= s;
+  }
+
+  let xs = [x, x + l, x + w - r, x + w];
+  let ys = [y, y + t, y + h - b, y + h];
+  // Images are uploaded top row first, so v = 0 is the top of the image
+  let us = [0.0, insets.left / iw, (iw - insets.right) / iw, 1.0];
+  let vs = [0.0, insets.top / ih, (ih - insets.bottom) / ih, 1.0];
+
+  for row in 0..3 {
+    for col in 0..3 {
+      if xs[col + 1] <= xs[col] || ys[row + 1] <= ys[row] { continue; }
+      let corners = [(col, row), (col + 1, row), (col + 1, row + 1),
+                     (col, row), (col + 1, row + 1), (col, row + 1)];
+      for &(cx, cy) in &corners {
+        let (px, py) = transform.apply(xs[cx], ys[cy]);
+        data.push(TexVertex {
+          position: [px, py],
+          tex_coords: [us[cx], vs[cy]],
+          opacity: opacity,
+        });
+      }
+    }
+  }
+}
@@ -0,0 +1,475 @@
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+
+/// A command making up a path. Points are in dp.
+#[derive(Clone, Copy, Debug)]
+enum PathCommand {
+  MoveTo((f32, f32)),
+  LineTo((f32, f32)),
+  /// Quadratic bezier: control point, end point
+  QuadTo((f32, f32), (f32, f32)),
+  /// Cubic bezier: control points, end point
+  CubicTo((f32, f32), (f32, f32), (f32, f32)),
+  /// Circular arc: centre, radius, start and end angles in radians
+  Arc((f32, f32), f32, f32, f32),
+  Close,
+}
+
+/// A vector path made of lines and curves, which can be filled or stroked
+/// with a Canvas. A path contains any number of subpaths, each started with
+/// move_to.
+#[derive(Clone, Debug)]
+pub struct Path {
+  commands: Vec<PathCommand>,
+}
+
+/// Decides which parts of a path are inside it when filled, for paths which
+/// overlap themselves.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FillRule {
+  /// A point is inside if the path winds around it a non-zero number of
+  /// times, counting clockwise and anticlockwise turns oppositely.
+  NonZero,
+  /// A point is inside if a line from it to infinity crosses the path an odd
+  /// number of times.
+  EvenOdd,
+}
+
+/// Shape drawn where two segments of a stroke meet.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineJoin {
+  /// Extend the outer edges until they meet. Falls back to Bevel if the
+  /// point would be further than miter_limit * width / 2 from the corner.
+  Miter,
+  Round,
+  /// Cut the corner off.
+  Bevel,
+}
+
+/// Shape drawn at the ends of an open stroke.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineCap {
+  /// End exactly at the end point.
+  Butt,
+  Round,
+  /// Extend past the end point by half the stroke width.
+  Square,
+}
+
+/// How to stroke a path.
+#[derive(Clone, Copy, Debug)]
+pub struct StrokeStyle {
+  /// Width of the stroke in dp.
+  pub width: f32,
+  pub join: LineJoin,
+  pub cap: LineCap,
+  pub miter_limit: f32,
+}
+
+impl StrokeStyle {
+  /// A stroke of the given width with miter joins and butt caps.
+  pub fn new(width: f32) -> StrokeStyle {
+    StrokeStyle {
+      width: width,
+      join: LineJoin::Miter,
+      cap: LineCap::Butt,
+      miter_limit: 4.0,
+    }
+  }
+}
+
+impl Path {
+  pub fn new() -> Path {
+    Path {
+      commands: Vec::new(),
+    }
+  }
+
+  /// Starts a new subpath at the given point.
+  pub fn move_to(&mut self, x: f32, y: f32) -> &mut Path {
+    self.commands.push(PathCommand::MoveTo((x, y)));
+    self
+  }
+
+  /// Adds a line from the current point.
+  pub fn line_to(&mut self, x: f32, y: f32) -> &mut Path {
+    self.commands.push(PathCommand::LineTo((x, y)));
+    self
+  }
+
+  /// Adds a quadratic bezier curve from the current point.
+  pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) -> &mut Path {
+    self.commands.push(PathCommand::QuadTo((cx, cy), (x, y)));
+    self
+  }
+
+  /// Adds a cubic bezier curve from the current point.
+  pub fn cubic_to(&mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32,
+                  x: f32, y: f32) -> &mut Path {
+    self.commands.push(PathCommand::CubicTo((c1x, c1y), (c2x, c2y), (x, y)));
+    self
+  }
+
+  /// Adds a circular arc. A line is added from the current point to the
+  /// start of the arc, if there is a current point. Angles are in radians,
+  /// where 0 points right and positive angles turn clockwise. The arc is
+  /// drawn clockwise if end_angle > start_angle, anticlockwise otherwise.
+  pub fn arc(&mut self, cx: f32, cy: f32, radius: f32,
+             start_angle: f32, end_angle: f32) -> &mut Path {
+    self.commands.push(PathCommand::Arc((cx, cy), radius, start_angle, end_angle));
+    self
+  }
+
+  /// Closes the current subpath with a line back to its start.
+  pub fn close(&mut self) -> &mut Path {
+    self.commands.push(PathCommand::Close);
+    self
+  }
+
+  /// Adds a closed rectangle subpath.
+  pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> &mut Path {
+    self.move_to(x, y).line_to(x + w, y).line_to(x + w, y + h)
+      .line_to(x, y + h).close()
+  }
+
+  /// Adds a closed circle subpath.
+  pub fn circle(&mut self, cx: f32, cy: f32, radius: f32) -> &mut Path {
+    self.move_to(cx + radius, cy).arc(cx, cy, radius, 0.0, 2.0 * PI).close()
+  }
+
+  /// Converts the path to polylines, approximating curves with lines no
+  /// further than tolerance from the curve. Returns each subpath's points,
+  /// and whether it was closed. Points which aren't finite are left out.
+  pub fn flatten(&self, tolerance: f32) -> Vec<(Vec<(f32, f32)>, bool)> {
+    let tolerance = tolerance.max(0.001);
+    let mut subpaths = Vec::new();
+    let mut points : Vec<(f32, f32)> = Vec::new();
+    for command in &self.commands {
+      match *command {
+        PathCommand::MoveTo(p) => {
+          if points.len() > 1 { subpaths.push((points, false)); }
+          points = Vec::new();
+          push_point(&mut points, p);
+        }
+        PathCommand::LineTo(p) => push_point(&mut points, p),
+        PathCommand::QuadTo(c, p) => {
+          if points.is_empty() { points.push(c); }
+          let p0 = *points.last().unwrap();
+          // Wang's formula for the number of segments
+          let dd = length(sub(add(p0, p), scale(c, 2.0)));
+          let n = segment_count((0.25 * dd / tolerance).sqrt());
+          for ii in 1..n + 1 {
+            let t = ii as f32 / n as f32;
+            let mt = 1.0 - t;
+            push_point(&mut points, add(add(scale(p0, mt * mt), scale(c, 2.0 * mt * t)),
+                                        scale(p, t * t)));
+          }
+        }
+        PathCommand::CubicTo(c1, c2, p) => {
+          if points.is_empty() { points.push(c1); }
+          let p0 = *points.last().unwrap();
+          let dd = length(sub(add(p0, c2), scale(c1, 2.0)))
+            .max(length(sub(add(c1, p), scale(c2, 2.0))));
+          let n = segment_count((0.75 * dd / tolerance).sqrt());
+          for ii in 1..n + 1 {
+            let t = ii as f32 / n as f32;
+            let mt = 1.0 - t;
+            push_point(&mut points,
+                       add(add(scale(p0, mt * mt * mt), scale(c1, 3.0 * mt * mt * t)),
+                           add(scale(c2, 3.0 * mt * t * t), scale(p, t * t * t))));
+          }
+        }
+        PathCommand::Arc(c, r, a0, a1) => {
+          let r = r.abs();
+          let sweep = a1 - a0;
+          // Largest angle a segment can cover while staying within tolerance
+          let max_angle = if r <= tolerance { PI / 2.0 }
+                          else { 2.0 * (1.0 - tolerance / r).acos() };
+          let n = segment_count(sweep.abs() / max_angle.max(0.01));
+          for ii in 0..n + 1 {
+            let a = a0 + sweep * ii as f32 / n as f32;
+            push_point(&mut points, (c.0 + r * a.cos(), c.1 + r * a.sin()));
+          }
+        }
+        PathCommand::Close => {
+          // The closing line is implied, so drop a last point which is
+          // already back at the start
+          if points.len() > 2 &&
+            length(sub(*points.last().unwrap(), points[0])) < 1e-4 {
+            points.pop();
+          }
+          if points.len() > 1 {
+            let start = points[0];
+            subpaths.push((points, true));
+            points = vec![start];
+          }
+        }
+      }
+    }
+    if points.len() > 1 { subpaths.push((points, false)); }
+    return subpaths;
+  }
+}
+
+fn segment_count(n: f32) -> usize {
+  (n.ceil() as usize).max(1).min(256)
+}
+
+/// Adds a point to a polyline, skipping it if it duplicates the last point or
+/// isn't finite.
+fn push_point(points: &mut Vec<(f32, f32)>, p: (f32, f32)) {
+  if !is_finite(p) { return; }
+  if points.last().map_or(false, |last| *last == p) { return; }
+  points.push(p);
+}
+
+fn add(a: (f32, f32), b: (f32, f32)) -> (f32, f32) { (a.0 + b.0, a.1 + b.1) }
+fn sub(a: (f32, f32), b: (f32, f32)) -> (f32, f32) { (a.0 - b.0, a.1 - b.1) }
+fn scale(a: (f32, f32), s: f32) -> (f32, f32) { (a.0 * s, a.1 * s) }
+fn length(a: (f32, f32)) -> f32 { (a.0 * a.0 + a.1 * a.1).sqrt() }
+fn cross(a: (f32, f32), b: (f32, f32)) -> f32 { a.0 * b.1 - a.1 * b.0 }
+fn is_finite(a: (f32, f32)) -> bool { a.0.is_finite() && a.1.is_finite() }
+
+/// Returns the unit vector pointing along a, rotated 90 degrees clockwise on
+/// screen, scaled by s.
+fn normal(a: (f32, f32), s: f32) -> (f32, f32) {
+  let len = length(a);
+  if len == 0.0 { return (0.0, 0.0); }
+  (-a.1 / len * s, a.0 / len * s)
+}
+
+/// An edge of a polygon, with y0 < y1 and the direction it originally ran in.
+struct Edge {
+  x0: f32, y0: f32,
+  x1: f32, y1: f32,
+  winding: i32,
+}
+
+impl Edge {
+  fn x_at(&self, y: f32) -> f32 {
+    self.x0 + (self.x1 - self.x0) * (y - self.y0) / (self.y1 - self.y0)
+  }
+}
+
+/// Tessellates polygons into triangles, filled with the given rule. The
+/// polygons are cut into horizontal slabs at every vertex and intersection,
+/// and within each slab the spans between edges which are inside are output
+/// as trapezoids. Edges with points which aren't finite are skipped. Returns
+/// a list of triangles, 3 points each.
+pub fn fill_polygons(polygons: &[Vec<(f32, f32)>], rule: FillRule) -> Vec<(f32, f32)> {
+  let mut edges = Vec::new();
+  let mut ys = Vec::new();
+  for polygon in polygons {
+    for ii in 0..polygon.len() {
+      let (a, b) = (polygon[ii], polygon[(ii + 1) % polygon.len()]);
+      if !is_finite(a) || !is_finite(b) { continue; }
+      ys.push(a.1);
+      if a.1 == b.1 { continue; } // Horizontal edges don't affect the fill
+      edges.push(if a.1 < b.1 {
+        Edge { x0: a.0, y0: a.1, x1: b.0, y1: b.1, winding: 1 }
+      } else {
+        Edge { x0: b.0, y0: b.1, x1: a.0, y1: a.1, winding: -1 }
+      });
+    }
+  }
+  // Edges can only cross at slab boundaries, so add a slab at every crossing
+  for ii in 0..edges.len() {
+    for jj in ii + 1..edges.len() {
+      let (e, f) = (&edges[ii], &edges[jj]);
+      let y0 = e.y0.max(f.y0);
+      let y1 = e.y1.min(f.y1);
+      if y0 >= y1 { continue; }
+      let (d0, d1) = (e.x_at(y0) - f.x_at(y0), e.x_at(y1) - f.x_at(y1));
+      if (d0 < 0.0 && d1 > 0.0) || (d0 > 0.0 && d1 < 0.0) {
+        ys.push(y0 + (y1 - y0) * d0 / (d0 - d1));
+      }
+    }
+  }
+  ys.retain(|y| y.is_finite());
+  ys.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+  ys.dedup();
+
+  let mut triangles = Vec::new();
+  for slab in ys.windows(2) {
+    let (ya, yb) = (slab[0], slab[1]);
+    let ym = (ya + yb) / 2.0;
+    let mut active : Vec<&Edge> = edges.iter()
+      .filter(|e| e.y0 <= ya && e.y1 >= yb).collect();
+    active.sort_by(|a, b| a.x_at(ym).partial_cmp(&b.x_at(ym))
+                   .unwrap_or(Ordering::Equal));
+
+    let mut winding = 0;
+    for ii in 0..active.len() {
+      winding += active[ii].winding;
+      let inside = match rule {
+        FillRule::NonZero => winding != 0,
+        FillRule::EvenOdd => winding % 2 != 0,
+      };
+      if !inside || ii + 1 >= active.len() { continue; }
+      let (l, r) = (active[ii], active[ii + 1]);
+      let (la, lb, ra, rb) = ((l.x_at(ya), ya), (l.x_at(yb), yb),
+                              (r.x_at(ya), ya), (r.x_at(yb), yb));
+      triangles.extend_from_slice(&[la, ra, rb, la, rb, lb]);
+    }
+  }
+  return triangles;
+}
+
+/// Returns the outline of a stroked polyline as a list of polygons, all wound
+/// the same way. Filling them with FillRule::NonZero gives their union, so
+/// overlapping parts of the stroke aren't drawn twice.
+pub fn stroke_polygons(points: &[(f32, f32)], closed: bool,
+                       style: &StrokeStyle) -> Vec<Vec<(f32, f32)>> {
+  let hw = style.width / 2.0;
+  let mut polygons = Vec::new();
+  if hw <= 0.0 || points.len() < 2 { return polygons; }
+  let n = points.len();
+  let segment_count = if closed { n } else { n - 1 };
+
+  // A quad for each segment, extended at the ends for square caps
+  for ii in 0..segment_count {
+    let (mut a, mut b) = (points[ii], points[(ii + 1) % n]);
+    let d = sub(b, a);
+    if !closed && style.cap == LineCap::Square {
+      let along = scale(d, hw / length(d));
+      if ii == 0 { a = sub(a, along); }
+      if ii == segment_count - 1 { b = add(b, along); }
+    }
+    let nm = normal(d, hw);
+    polygons.push(vec![add(a, nm), add(b, nm), sub(b, nm), sub(a, nm)]);
+  }
+
+  // Joins at each corner
+  let corners = if closed { 0..n } else { 1..n - 1 };
+  for ii in corners {
+    let p = points[ii];
+    let d0 = sub(p, points[(ii + n - 1) % n]);
+    let d1 = sub(points[(ii + 1) % n], p);
+    let turn = cross(d0, d1);
+    if turn == 0.0 { continue; }
+    // The join fills the gap on the outside of the turn
+    let side = if turn > 0.0 { -hw } else { hw };
+    let (n0, n1) = (normal(d0, side), normal(d1, side));
+    match style.join {
+      LineJoin::Round => polygons.push(circle_polygon(p, hw)),
+      LineJoin::Bevel => polygons.push(vec![p, add(p, n0), add(p, n1)]),
+      LineJoin::Miter => {
+        let mid = add(n0, n1);
+        let cos_half = length(mid) / (2.0 * hw);
+        if cos_half > 0.0 && 1.0 / cos_half <= style.miter_limit {
+          let miter = scale(mid, hw / (length(mid) * cos_half));
+          polygons.push(vec![p, add(p, n0), add(p, miter), add(p, n1)]);
+        }
+        else {
+          polygons.push(vec![p, add(p, n0), add(p, n1)]);
+        }
+      }
+    }
+  }
+
+  if !closed && style.cap == LineCap::Round {
+    polygons.push(circle_polygon(points[0], hw));
+    polygons.push(circle_polygon(points[n - 1], hw));
+  }
+
+  // Make every polygon wind the same way
+  for polygon in &mut polygons {
+    let mut area = 0.0;
+    for ii in 0..polygon.len() {
+      area += cross(polygon[ii], polygon[(ii + 1) % polygon.len()]);
+    }
+    if area < 0.0 { polygon.reverse(); }
+  }
+  return polygons;
+}
+
+fn circle_polygon(c: (f32, f32), r: f32) -> Vec<(f32, f32)> {
+  let segments = ((r.sqrt() * 4.0).ceil() as usize).max(8).min(64);
+  (0..segments).map(|ii| {
+    let a = 2.0 * PI * ii as f32 / segments as f32;
+    (c.0 + r * a.cos(), c.1 + r * a.sin())
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Total area covered by a list of triangles.
+  fn area(triangles: &[(f32, f32)]) -> f32 {
+    triangles.chunks(3).map(|t| {
+      (cross(sub(t[1], t[0]), sub(t[2], t[0])) / 2.0).abs()
+    }).sum()
+  }
+
+  fn fill(path: &Path, rule: FillRule) -> Vec<(f32, f32)> {
+    let polygons : Vec<Vec<(f32, f32)>> = path.flatten(0.1).into_iter()
+      .map(|(points, _)| points).collect();
+    fill_polygons(&polygons, rule)
+  }
+
+  /// Area of the union of a stroked corner, (0, 0) -> (10, 0) -> (10, 10).
+  fn corner_area(join: LineJoin, miter_limit: f32) -> f32 {
+    let mut style = StrokeStyle::new(2.0);
+    style.join = join;
+    style.miter_limit = miter_limit;
+    let polygons = stroke_polygons(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)],
+                                   false, &style);
+    area(&fill_polygons(&polygons, FillRule::NonZero))
+  }
+
+  #[test]
+  fn fill_convex() {
+    let mut path = Path::new();
+    path.move_to(0.0, 0.0).line_to(10.0, 0.0).line_to(5.0, 10.0).close();
+    let triangles = fill(&path, FillRule::NonZero);
+    assert_eq!(triangles.len() % 3, 0);
+    assert!((area(&triangles) - 50.0).abs() < 1e-3);
+    for p in &triangles {
+      assert!(p.1 >= 0.0 && p.1 <= 10.0);
+    }
+  }
+
+  #[test]
+  fn fill_self_intersecting() {
+    // A bow tie, two triangles meeting at (5, 5)
+    let mut path = Path::new();
+    path.move_to(0.0, 0.0).line_to(10.0, 10.0).line_to(10.0, 0.0)
+      .line_to(0.0, 10.0).close();
+    assert!((area(&fill(&path, FillRule::NonZero)) - 50.0).abs() < 1e-3);
+    assert!((area(&fill(&path, FillRule::EvenOdd)) - 50.0).abs() < 1e-3);
+
+    // Overlapping squares wound the same way, the overlap is a hole only
+    // with the even-odd rule
+    let mut path = Path::new();
+    path.rect(0.0, 0.0, 10.0, 10.0).rect(5.0, 5.0, 10.0, 10.0);
+    assert!((area(&fill(&path, FillRule::NonZero)) - 175.0).abs() < 1e-3);
+    assert!((area(&fill(&path, FillRule::EvenOdd)) - 150.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn fill_skips_non_finite() {
+    let mut path = Path::new();
+    path.move_to(0.0, 0.0).line_to(10.0, 0.0).line_to(::std::f32::NAN, 5.0)
+      .line_to(10.0, 10.0).line_to(0.0, 10.0).close();
+    assert!((area(&fill(&path, FillRule::NonZero)) - 100.0).abs() < 1e-3);
+
+    let polygons = vec![vec![(0.0, 0.0), (::std::f32::NAN, ::std::f32::NAN),
+                             (10.0, 10.0), (0.0, ::std::f32::INFINITY)]];
+    fill_polygons(&polygons, FillRule::EvenOdd);
+  }
+
+  #[test]
+  fn stroke_joins() {
+    // The two segments cover 39 square dp between them, the joins fill the
+    // outside of the corner on top
+    let miter = corner_area(LineJoin::Miter, 4.0);
+    let bevel = corner_area(LineJoin::Bevel, 4.0);
+    let round = corner_area(LineJoin::Round, 4.0);
+    assert!((miter - 40.0).abs() < 1e-3);
+    assert!((bevel - 39.5).abs() < 1e-3);
+    assert!(round > bevel && round < miter);
+    // A right angle's miter is sqrt(2) widths long, past a limit of 1
+    assert!((corner_area(LineJoin::Miter, 1.0) - bevel).abs() < 1e-3);
+  }
+}
@@ -0,0 +1,226 @@
+use std::f32::consts::PI;
+
+use common::color::RGBAf32;
+use entity::core::{ComponentAABB, ComponentBackground, ComponentShadow, Gradient};
+use renderer::Vertex;
+
+/// Number of concentric rings used when filling a shape with a radial
+/// gradient. Colours are interpolated linearly between vertices, so a radial
+/// gradient needs more vertices than just the centre and the edge.
+const RADIAL_RINGS: usize = 8;
+
+/// Number of bands a shadow's blurred edge is made of.
+const SHADOW_BANDS: usize = 6;
+
+/// Describes how to colour the vertices of a shape.
+pub enum Paint<'a> {
+  Solid(&'a RGBAf32),
+  /// A gradient, along with the (x, y, w, h) rect its coordinates are
+  /// relative to.
+  Gradient(&'a Gradient, (f32, f32, f32, f32)),
+}
+
+impl<'a> Paint<'a> {
+  /// Get the colour of this paint at the given position, with the alpha
+  /// multiplied by alpha.
+  pub fn color_at(&self, x: f32, y: f32, alpha: f32) -> [f32; 4] {
+    let c = match *self {
+      Paint::Solid(c) => c.clone(),
+      Paint::Gradient(g, (rx, ry, rw, rh)) => {
+        match *g {
+          Gradient::Linear {start, end, ref start_color, ref end_color} => {
+            let (sx, sy) = (rx + start.0 * rw, ry + start.1 * rh);
+            let (dx, dy) = ((end.0 - start.0) * rw, (end.1 - start.1) * rh);
+            let len_sq = dx * dx + dy * dy;
+            let t = if len_sq <= 0.0 { 0.0 }
+            else { ((x - sx) * dx + (y - sy) * dy) / len_sq };
+            start_color.lerp(end_color, t.max(0.0).min(1.0))
+          }
+          Gradient::Radial {centre, radius, ref inner_color, ref outer_color} => {
+            let (cx, cy) = (rx + centre.0 * rw, ry + centre.1 * rh);
+            let r = radius * rw.max(rh);
+            let d = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+            let t = if r <= 0.0 { 1.0 } else { d / r };
+            inner_color.lerp(outer_color, t.max(0.0).min(1.0))
+          }
+        }
+      }
+    };
+    [c.r, c.g, c.b, c.a * alpha]
+  }
+}
+
+/// A ring of a shape. The outline of the shape is offset outwards by
+/// 'offset' pixels (inwards if negative), then coloured with the paint.
+struct Band<'a> {
+  offset: f32,
+  paint: &'a Paint<'a>,
+  alpha: f32,
+}
+
+/// Number of segments used to approximate a corner of the given radius.
+fn corner_segments(radius: f32) -> usize {
+  let n = ((radius + 1.0).sqrt() * 2.0).ceil() as usize;
+  if n < 2 { 2 } else if n > 16 { 16 } else { n }
+}
+
+/// Generates the outline of a rounded rectangle, clockwise from the top left
+/// corner, expanded outwards by offset pixels (shrunk if offset is negative).
+/// The number of points only depends on the radii, not the offset, so
+/// outlines of the same rect at different offsets can be stitched together
+/// point by point.
+pub fn rounded_rect_contour(x: f32, y: f32, w: f32, h: f32,
+                            radii: [f32; 4], offset: f32) -> Vec<(f32, f32)> {
+  let (x, y, w, h) = (x - offset, y - offset, w + 2.0 * offset, h + 2.0 * offset);
+  let mut points = Vec::new();
+  // Corner centres are given relative to the corner of the rect, along with
+  // the start angle of the arc.
+  let corners = [((x, y), (1.0, 1.0), PI),
+                 ((x + w, y), (-1.0, 1.0), PI * 1.5),
+                 ((x + w, y + h), (-1.0, -1.0), 0.0),
+                 ((x, y + h), (1.0, -1.0), PI * 0.5)];
+  for ii in 0..4 {
+    let ((px, py), (sx, sy), a0) = corners[ii];
+    let segments = corner_segments(radii[ii]);
+    let r = (radii[ii] + offset).max(0.0);
+    let (cx, cy) = (px + sx * r, py + sy * r);
+    for jj in 0..segments + 1 {
+      let a = a0 + (PI * 0.5) * jj as f32 / segments as f32;
+      points.push((cx + r * a.cos(), cy + r * a.sin()));
+    }
+  }
+  return points;
+}
+
+/// Clamps corner radii so that they are never larger than half of the rect's
+/// smallest side, and never negative.
+pub fn clamp_radii(w: f32, h: f32, radii: [f32; 4]) -> [f32; 4] {
+  let max = (w.min(h) / 2.0).max(0.0);
+  let mut clamped = radii;
+  for r in &mut clamped {
+    *r = r.max(0.0).min(max);
+  }
+  return clamped;
+}
+
+fn push_vertex(data: &mut Vec<Vertex>, p: (f32, f32), band: &Band) {
+  data.push(Vertex {
+    position: [p.0, p.1],
+    color: band.paint.color_at(p.0, p.1, band.alpha),
+  });
+}
+
+/// Pushes triangles for a rounded rect made up of the given bands, ordered
+/// from the innermost band outwards. The innermost band is filled as a fan,
+/// and each following band is stitched to the previous one with a strip.
+fn push_bands(data: &mut Vec<Vertex>, rect: (f32, f32, f32, f32),
+              radii: [f32; 4], bands: &[Band]) {
+  if bands.is_empty() { return; }
+  let (x, y, w, h) = rect;
+  let centre = (x + w / 2.0, y + h / 2.0);
+  let contours : Vec<Vec<(f32, f32)>> = bands.iter()
+    .map(|b| rounded_rect_contour(x, y, w, h, radii, b.offset)).collect();
+
+  // Fill the innermost band
+  let inner = &contours[0];
+  for ii in 0..inner.len() {
+    let next = (ii + 1) % inner.len();
+    push_vertex(data, centre, &bands[0]);
+    push_vertex(data, inner[ii], &bands[0]);
+    push_vertex(data, inner[next], &bands[0]);
+  }
+
+  // Stitch each band to the one before it
+  for kk in 1..contours.len() {
+    let (a, b) = (&contours[kk - 1], &contours[kk]);
+    let (band_a, band_b) = (&bands[kk - 1], &bands[kk]);
+    for ii in 0..a.len() {
+      let next = (ii + 1) % a.len();
+      push_vertex(data, a[ii], band_a);
+      push_vertex(data, a[next], band_a);
+      push_vertex(data, b[next], band_b);
+      push_vertex(data, a[ii], band_a);
+      push_vertex(data, b[next], band_b);
+      push_vertex(data, b[ii], band_b);
+    }
+  }
+}
+
+/// Pushes triangles for the given background component, drawn inside the
+/// given AABB. Edges are faded out over aa_width, which should be the size of
+/// one physical pixel.
+pub fn push_background(data: &mut Vec<Vertex>, aabb: &ComponentAABB,
+                       bg: &ComponentBackground, aa_width: f32) {
+  let (x, y, w, h) = (aabb.x, aabb.y, aabb.w, aabb.h);
+  if w <= 0.0 || h <= 0.0 { return; }
+  let half = w.min(h) / 2.0;
+  let radii = clamp_radii(w, h, bg.corner_radii);
+  let border_width = bg.border_width.max(0.0).min(half);
+  let half_aa = aa_width / 2.0;
+
+  let fill = match bg.gradient {
+    Some(ref g) => Paint::Gradient(g, (x, y, w, h)),
+    None => Paint::Solid(&bg.color),
+  };
+  let border = Paint::Solid(&bg.border_color);
+
+  // Offset at which the fill ends and the border (or the edge) begins
+  let fill_end = if border_width > 0.0 { -border_width - half_aa }
+                 else { -half_aa };
+  let fill_end = fill_end.max(-half);
+
+  let mut bands = Vec::new();
+  match bg.gradient {
+    Some(Gradient::Radial {..}) => {
+      for ii in 0..RADIAL_RINGS {
+        let t = ii as f32 / (RADIAL_RINGS - 1) as f32;
+        bands.push(Band { offset: -half + (fill_end + half) * t,
+                          paint: &fill, alpha: 1.0 });
+      }
+    }
+    _ => bands.push(Band { offset: fill_end, paint: &fill, alpha: 1.0 }),
+  }
+  if border_width > 0.0 {
+    // Blend from the fill into the border, then fade the border out at the
+    // edge
+    let border_start = (-border_width + half_aa).min(-half_aa).max(fill_end);
+    bands.push(Band { offset: border_start, paint: &border, alpha: 1.0 });
+    bands.push(Band { offset: -half_aa, paint: &border, alpha: 1.0 });
+    bands.push(Band { offset: half_aa, paint: &border, alpha: 0.0 });
+  }
+  else {
+    bands.push(Band { offset: half_aa, paint: &fill, alpha: 0.0 });
+  }
+
+  push_bands(data, (x, y, w, h), radii, &bands);
+}
+
+/// Pushes triangles for a shadow cast by an entity with the given AABB and
+/// corner radii. The edge fades out over the blur radius, centred on the
+/// shadow's outline, approximating a gaussian blur.
+pub fn push_shadow(data: &mut Vec<Vertex>, aabb: &ComponentAABB,
+                   shadow: &ComponentShadow, radii: [f32; 4], aa_width: f32) {
+  let spread = shadow.spread;
+  let (x, y) = (aabb.x + shadow.offset.0 - spread, aabb.y + shadow.offset.1 - spread);
+  let (w, h) = (aabb.w + 2.0 * spread, aabb.h + 2.0 * spread);
+  if w <= 0.0 || h <= 0.0 { return; }
+  let mut radii = clamp_radii(aabb.w, aabb.h, radii);
+  for r in &mut radii {
+    if *r > 0.0 { *r = (*r + spread).max(0.0); }
+  }
+  let radii = clamp_radii(w, h, radii);
+
+  let paint = Paint::Solid(&shadow.color);
+  // Never fade out over less than a pixel, and don't shrink past the centre
+  let blur = shadow.blur_radius.max(aa_width);
+  let inner = (-blur / 2.0).max(-w.min(h) / 2.0);
+  let outer = blur / 2.0;
+  let mut bands = Vec::with_capacity(SHADOW_BANDS + 1);
+  for ii in 0..SHADOW_BANDS + 1 {
+    let t = ii as f32 / SHADOW_BANDS as f32;
+    // Smoothstep falloff, close to the shape of a blurred edge
+    let alpha = 1.0 - t * t * (3.0 - 2.0 * t);
+    bands.push(Band { offset: inner + (outer - inner) * t, paint: &paint, alpha: alpha });
+  }
+  push_bands(data, (x, y, w, h), radii, &bands);
+}
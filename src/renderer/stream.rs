@@ -0,0 +1,99 @@
+use std::mem;
+use glium;
+use glium::backend::Facade;
+
+/// Changed vertex ranges closer together than this many vertices are merged
+/// into one upload, since many small buffer writes are slower than one
+/// slightly larger write.
+const UPLOAD_MERGE_GAP: usize = 64;
+
+/// A vertex buffer which is kept between frames. Each frame the vertex data is
+/// built up on the CPU, then only the ranges which differ from the last frame
+/// are uploaded.
+pub struct StreamBuffer<T: glium::Vertex + PartialEq> {
+  /// The GPU buffer. Grows when a frame has more vertices than it can hold.
+  vbo: Option<glium::VertexBuffer<T>>,
+  /// Vertex data for the frame being built.
+  pub data: Vec<T>,
+  /// Vertex data from the last frame, i.e what the vertex buffer contains.
+  prev_data: Vec<T>,
+}
+
+impl<T: glium::Vertex + PartialEq> StreamBuffer<T> {
+  pub fn new() -> StreamBuffer<T> {
+    StreamBuffer {
+      vbo: None,
+      data: Vec::new(),
+      prev_data: Vec::new(),
+    }
+  }
+
+  /// Clears the vertex data, ready to build the next frame. The current data
+  /// is kept to diff against, so this should only be called after uploading.
+  pub fn clear(&mut self) {
+    mem::swap(&mut self.data, &mut self.prev_data);
+    self.data.clear();
+  }
+
+  /// Returns the vertex data from the last frame.
+  pub fn prev_data(&self) -> &[T] {
+    &self.prev_data
+  }
+
+  pub fn len(&self) -> usize {
+    self.data.len()
+  }
+
+  /// Uploads the vertex data to the GPU. The vertex buffer is only recreated
+  /// when it is too small, otherwise just the ranges which differ from the
+  /// last upload are written. Returns the number of bytes uploaded.
+  pub fn upload<F: Facade>(&mut self, facade: &F) -> usize {
+    let len = self.data.len();
+    let vertex_size = mem::size_of::<T>();
+    let mut uploaded = 0;
+
+    if len > 0 {
+      let has_capacity = self.vbo.is_some() &&
+        self.vbo.as_ref().unwrap().len() >= len;
+      if !has_capacity {
+        let vbo = glium::VertexBuffer::empty_dynamic(
+          facade, len.next_power_of_two()).unwrap();
+        vbo.slice(0..len).unwrap().write(&self.data);
+        self.vbo = Some(vbo);
+        uploaded = len * vertex_size;
+      }
+      else {
+        let vbo = self.vbo.as_ref().unwrap();
+        for (start, end) in changed_ranges(&self.prev_data, &self.data) {
+          vbo.slice(start..end).unwrap().write(&self.data[start..end]);
+          uploaded += (end - start) * vertex_size;
+        }
+      }
+    }
+    return uploaded;
+  }
+
+  /// Returns a slice of the uploaded vertex buffer.
+  pub fn slice<'a>(&'a self, start: usize, count: usize)
+                   -> Option<glium::vertex::VertexBufferSlice<'a, T>> {
+    if self.vbo.is_none() { return None; }
+    self.vbo.as_ref().unwrap().slice(start..start + count)
+  }
+}
+
+/// Compares the last uploaded vertex data with the new data, and returns a
+/// list of [start, end) ranges of vertices which need uploading.
+fn changed_ranges<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+  let mut ranges : Vec<(usize, usize)> = Vec::new();
+  for ii in 0..new.len() {
+    if ii < old.len() && old[ii] == new[ii] { continue; }
+    if let Some(last) = ranges.last_mut() {
+      if ii - last.1 <= UPLOAD_MERGE_GAP {
+        last.1 = ii + 1;
+        continue;
+      }
+    }
+    ranges.push((ii, ii + 1));
+  }
+  return ranges;
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use common::color::RGBAf32;
+use common::rect::Insets;
+use entity::EntityID;
+use entity::core::{ComponentBackground, ComponentPadding, ComponentTextStyle};
+use view::{View, Layer};
+
+/// A named set of visual properties, applied to every entity whose
+/// ComponentStyle refers to it. Properties which are None are left alone, so
+/// they can still be set per entity. This also means that when the theme is
+/// switched, a property the new style leaves None keeps the value the old
+/// theme gave it, so styles meant to replace each other should set the same
+/// properties.
+#[derive(Clone, Default)]
+pub struct Style {
+  /// Sets the colour of the entity's ComponentBackground, adding one if
+  /// needed.
+  pub background: Option<RGBAf32>,
+  /// Sets all four corner radii of the entity's ComponentBackground, in dp.
+  pub corner_radius: Option<f32>,
+  /// Sets the entity's ComponentPadding.
+  pub padding: Option<Insets>,
+  /// Set the entity's ComponentTextStyle, adding one if needed.
+  pub text_color: Option<RGBAf32>,
+  pub font: Option<String>,
+  pub font_size: Option<f32>,
+}
+
+impl Style {
+  pub fn new() -> Style {
+    Style::default()
+  }
+}
+
+/// A collection of named styles, e.g a light or dark theme. Set the theme
+/// with LibState::set_theme.
+pub struct Theme {
+  styles: HashMap<String, Style>,
+}
+
+impl Theme {
+  pub fn new() -> Theme {
+    Theme {
+      styles: HashMap::new(),
+    }
+  }
+
+  /// Adds a style to the theme, replacing any style with the same name.
+  pub fn set_style(&mut self, name: &str, style: Style) {
+    self.styles.insert(name.to_string(), style);
+  }
+
+  pub fn get_style(&self, name: &str) -> Option<&Style> {
+    self.styles.get(name)
+  }
+}
+
+/// Applies a theme to every styled entity in a view, including entities in
+/// nested layers. Entities referring to a style the theme doesn't have are
+/// left alone.
+pub fn apply_theme(theme: &Theme, view: &mut View) {
+  for layer in &mut view.layers {
+    apply_theme_to_layer(theme, layer);
+  }
+}
+
+fn apply_theme_to_layer(theme: &Theme, layer: &mut Layer) {
+  let styled : Vec<(EntityID, String)> = layer.component_style.into_iter()
+    .map(|s| (s.entity_id, s.name.clone())).collect();
+  let mut changed = false;
+  for (e_id, name) in styled {
+    let style = theme.get_style(&name);
+    if style.is_none() { continue; }
+    apply_style(style.unwrap(), layer, e_id);
+    changed = true;
+  }
+  if changed {
+    layer.mark_dirty();
+  }
+
+  for l in &mut layer.component_layer {
+    apply_theme_to_layer(theme, l);
+  }
+}
+
+/// Sets an entity's components from a style.
+fn apply_style(style: &Style, layer: &mut Layer, e_id: EntityID) {
+  if style.background.is_some() || style.corner_radius.is_some() {
+    let mut bg = match layer.component_background.get_component(e_id) {
+      Some(bg) => bg.clone(),
+      None => ComponentBackground {
+        entity_id: e_id,
+        color: RGBAf32::new(0.0, 0.0, 0.0, 0.0),
+        gradient: None,
+        border_width: 0.0,
+        border_color: RGBAf32::new(0.0, 0.0, 0.0, 0.0),
+        corner_radii: [0.0; 4],
+      },
+    };
+    if style.background.is_some() {
+      bg.color = style.background.clone().unwrap();
+    }
+    if style.corner_radius.is_some() {
+      bg.corner_radii = [style.corner_radius.unwrap(); 4];
+    }
+    layer.component_background.add_component(bg);
+  }
+
+  if style.padding.is_some() {
+    layer.component_padding.add_component(ComponentPadding {
+      entity_id: e_id,
+      padding: style.padding.unwrap(),
+    });
+  }
+
+  if style.text_color.is_some() || style.font.is_some() || style.font_size.is_some() {
+    let mut text = match layer.component_text_style.get_component(e_id) {
+      Some(text) => text.clone(),
+      None => ComponentTextStyle::new(e_id),
+    };
+    if style.text_color.is_some() {
+      text.color = style.text_color.clone().unwrap();
+    }
+    if style.font.is_some() {
+      text.font = style.font.clone().unwrap();
+    }
+    if style.font_size.is_some() {
+      text.size = style.font_size.unwrap();
+    }
+    layer.component_text_style.add_component(text);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use entity::core::ComponentStyle;
+
+  fn theme(background: RGBAf32) -> Theme {
+    let mut style = Style::new();
+    style.background = Some(background);
+    style.corner_radius = Some(4.0);
+    let mut theme = Theme::new();
+    theme.set_style("card", style);
+    theme
+  }
+
+  #[test]
+  fn switching_theme_replaces_background() {
+    let e_id = EntityID(1);
+    let mut layer = Layer::new();
+    layer.component_style.add_component(ComponentStyle {
+      entity_id: e_id,
+      name: "card".to_string(),
+    });
+    let mut view = View::new();
+    view.layers.push(layer);
+
+    apply_theme(&theme(RGBAf32::new(1.0, 1.0, 1.0, 1.0)), &mut view);
+    apply_theme(&theme(RGBAf32::new(0.1, 0.1, 0.1, 1.0)), &mut view);
+    apply_theme(&theme(RGBAf32::new(0.2, 0.2, 0.2, 1.0)), &mut view);
+
+    let backgrounds = &view.layers[0].component_background;
+    assert_eq!(backgrounds.len(), 1);
+    let bg = backgrounds.get_component(e_id).unwrap();
+    assert_eq!((bg.color.r, bg.color.g, bg.color.b), (0.2, 0.2, 0.2));
+    assert_eq!(bg.corner_radii, [4.0; 4]);
+  }
+}
@@ -0,0 +1,70 @@
+use std::cell::Cell;
+use time;
+use LibState;
+
+/// Identifies a timer added with LibState::add_timer.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TimerID (pub u32);
+
+/// Trait which defines a 'TimerListener', a listener who is called when a
+/// timer fires.
+pub trait TimerListener {
+  /// Called once the timer's delay has passed. A frame is always run after
+  /// timers fire, so changes made to views here are drawn.
+  fn on_timer(&self, lib_state: &mut LibState, timer: TimerID);
+}
+
+/// A one-shot timer.
+pub struct Timer<'a> {
+  pub id: TimerID,
+  /// Performance counter time the timer fires at, in nanoseconds.
+  pub deadline_nanos: u64,
+  pub listener: &'a TimerListener,
+}
+
+/// Source of time for the engine: frame deltas, animations and timers. Apps
+/// can replace the clock with LibState::set_clock, e.g with a ManualClock to
+/// step animations deterministically in tests.
+pub trait Clock {
+  /// Returns the current time in nanoseconds. Only differences between times
+  /// are meaningful.
+  fn now_nanos(&self) -> u64;
+}
+
+/// Clock reading the system's performance counter. The default clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now_nanos(&self) -> u64 {
+    time::precise_time_ns()
+  }
+}
+
+/// Clock which only moves when told to.
+pub struct ManualClock {
+  nanos: Cell<u64>,
+}
+
+impl ManualClock {
+  pub fn new() -> ManualClock {
+    ManualClock {
+      nanos: Cell::new(0),
+    }
+  }
+
+  /// Moves the clock forwards by the given number of nanoseconds.
+  pub fn advance(&self, nanos: u64) {
+    self.nanos.set(self.nanos.get() + nanos);
+  }
+
+  /// Moves the clock forwards by the given number of seconds.
+  pub fn advance_secs(&self, secs: f64) {
+    self.advance((secs * 1000000000.0) as u64);
+  }
+}
+
+impl Clock for ManualClock {
+  fn now_nanos(&self) -> u64 {
+    self.nanos.get()
+  }
+}
@@ -19,39 +19,11 @@ impl<T : Component> ComponentList<T> {
   /// already exists, replace it. O(log(n)).
   /// @param component The component to add to the list.
   pub fn add_component(&mut self, component : T) {
-    if self.list.len() == 0 {
-      self.list.push(component);
-      return;
-    }
     let entity_id = component.get_entity_id();
-    let (mut l_bound, mut u_bound) = (0, self.list.len() - 1);
-    let mut target;
-    loop {
-      if u_bound - l_bound <= 1 {
-        if u_bound == self.list.len()-1 && 
-          self.list[u_bound].get_entity_id() < entity_id { // End of the list?
-            self.list.push(component);
-          }
-        else if l_bound == 0 &&
-          self.list[l_bound].get_entity_id() > entity_id { // Start of list?
-            self.list.insert(0, component);
-          }
-        else { // In between u_bound and l_bound
-          self.list.insert(u_bound, component);
-        }
-        break;
-      }
-      target = (u_bound + l_bound) / 2;
-      if self.list[target].get_entity_id() > entity_id {
-        u_bound = target;
-      }
-      else if self.list[target].get_entity_id() < entity_id {
-        l_bound = target;
-      }
-      else { // Same entity ID, replace the component at this index
-        self.list[target] = component;
-        return;
-      }
+    match self.list.binary_search_by_key(&entity_id.0, |c| c.get_entity_id().0) {
+      // Same entity ID, replace the component at this index
+      Ok(index) => self.list[index] = component,
+      Err(index) => self.list.insert(index, component),
     }
   }
 
@@ -117,6 +89,13 @@ impl<T : Component> ComponentList<T> {
   pub fn remove(&mut self, index: usize) {
     self.list.remove(index);
   }
+
+  /// Keeps only the components the predicate returns true for, removing the
+  /// rest. The remaining components stay in order. Unlike removing by index,
+  /// this is safe to use for several components at once.
+  pub fn retain<F>(&mut self, f: F) where F: FnMut(&T) -> bool {
+    self.list.retain(f);
+  }
 }
 
 /// Implement the IntoIterator for ComponentList ref, lets us iterate over the list
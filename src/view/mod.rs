@@ -4,7 +4,13 @@ use entity::core::*;
 use entity::animation::*;
 use entity::{Component, EntityID};
 use view::component_list::ComponentList;
+use common::rect::Rect;
+use common::transform::Affine2;
+use renderer::LayerCache;
+use std::cell::{Cell, RefCell};
 use layout::manager;
+use animation;
+use common::vec;
 
 /// Trait which defines a 'ViewListener', a listener who is called when a view
 /// is added or removed from the view stack.
@@ -15,20 +21,95 @@ pub trait ViewListener {
   fn on_hide(&self, view : &View);
 }
 
+/// Controls how a layer is drawn.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CacheMode {
+  /// The layer is drawn directly every frame.
+  Direct,
+  /// The layer and all of its nested layers are drawn once into an offscreen
+  /// texture, which is then composited every frame until the layer is marked
+  /// dirty. Use this for complex layers which rarely change.
+  Cached,
+}
+
+/// State a layer inherits from the entity it is nested in, and that entity's
+/// parents.
+#[derive(Clone, Copy)]
+pub struct Inherited {
+  pub transform: Affine2,
+  pub opacity: f32,
+}
+
+impl Inherited {
+  /// The state of root layers, i.e no transform and fully opaque.
+  pub fn root() -> Inherited {
+    Inherited {
+      transform: Affine2::identity(),
+      opacity: 1.0,
+    }
+  }
+}
+
 /// Structure which contains the data for a view. It is essentially an ECS, with clipping
 /// information.
+///
+/// All layers share the view's coordinate space, including nested layers: an
+/// entity at (10, 10) is 10dp from the top left of the view no matter how
+/// deeply its layer is nested. A nested layer's entity AABB only acts as a
+/// clip rect - its contents are clipped to the intersection of that AABB and
+/// the clip rects of all the layers it is nested in. Rendering and input
+/// hit-testing both follow this.
 pub struct Layer {
   pub component_debug_draw : ComponentList<ComponentDebugDraw>,
+  pub component_shadow : ComponentList<ComponentShadow>,
+  pub component_background : ComponentList<ComponentBackground>,
+  pub component_nine_patch : ComponentList<ComponentNinePatch>,
+  pub component_padding : ComponentList<ComponentPadding>,
+  pub component_custom_draw : ComponentList<ComponentCustomDraw>,
+  pub component_style : ComponentList<ComponentStyle>,
+  pub component_text_style : ComponentList<ComponentTextStyle>,
+  pub component_transform : ComponentList<ComponentTransform>,
+  pub component_opacity : ComponentList<ComponentOpacity>,
   pub component_aabb : ComponentList<ComponentAABB>,
   pub component_container : ComponentList<ComponentContainer>,
   pub component_trigger : ComponentList<ComponentTrigger>,
   pub component_touch_scroll : ComponentList<ComponentTouchScroll>,
   pub component_anim_translate : ComponentList<ComponentAnimTranslate>,
+  pub component_anim_property : ComponentList<ComponentAnimProperty>,
+  pub component_anim_timeline : ComponentList<ComponentAnimTimeline>,
+  pub component_anim_decay : ComponentList<ComponentAnimDecay>,
+  pub component_anim_playback : ComponentList<ComponentAnimPlayback>,
   pub component_scroll_snap : ComponentList<ComponentScrollSnap>,
   pub component_layer : ComponentList<Layer>,
+  /// Optional entity whose AABB is kept the size of the window. Only used on
+  /// root layers (layers directly in a view). Whenever the window is resized,
+  /// e.g when the device is rotated, this entity's AABB is set to cover the
+  /// whole window before the view is laid out.
+  pub fill_window : Option<EntityID>,
   /// Optional entity ID association. If this layer has an AABB associated with
-  /// it (for GL scissor clipping), then this ID will be Some. Otherwise, None.
+  /// it (for scissor clipping), then this ID will be Some. Otherwise, None.
   pub entity_id : Option<EntityID>,
+
+  /// Whether this layer is drawn directly, or cached in a texture.
+  pub cache_mode : CacheMode,
+  /// Opacity the layer's texture is composited with. Only used when cached.
+  pub composite_opacity : f32,
+  /// Offset the layer's texture is composited at. Only used when cached.
+  pub composite_offset : (f32, f32),
+  /// Scale (around its centre) the layer's texture is composited at. Only used
+  /// when cached.
+  pub composite_scale : f32,
+  /// The layer's offscreen texture, when cached.
+  cache : RefCell<LayerCache>,
+  /// True if something in this layer has changed since it was last drawn into
+  /// its cache.
+  dirty : Cell<bool>,
+  /// Animation events waiting to be sent to the app's AnimListeners.
+  anim_events : Vec<AnimEvent>,
+  /// Each contained entity's container parent, as (child, parent) pairs
+  /// sorted by child. Rebuilt whenever the layer is laid out, or on first use
+  /// after invalidate_parents.
+  parents : RefCell<Option<Vec<(u16, EntityID)>>>,
 }
 
 impl Component for Layer {
@@ -46,14 +127,478 @@ impl Layer {
   pub fn new() -> Layer {
     Layer {
       component_debug_draw : ComponentList::new(),
+      component_shadow : ComponentList::new(),
+      component_background : ComponentList::new(),
+      component_nine_patch : ComponentList::new(),
+      component_padding : ComponentList::new(),
+      component_custom_draw : ComponentList::new(),
+      component_style : ComponentList::new(),
+      component_text_style : ComponentList::new(),
+      component_transform : ComponentList::new(),
+      component_opacity : ComponentList::new(),
       component_container : ComponentList::new(),
       component_aabb : ComponentList::new(),
       component_trigger : ComponentList::new(),
       component_touch_scroll : ComponentList::new(),
       component_anim_translate : ComponentList::new(),
+      component_anim_property : ComponentList::new(),
+      component_anim_timeline : ComponentList::new(),
+      component_anim_decay : ComponentList::new(),
+      component_anim_playback : ComponentList::new(),
       component_scroll_snap : ComponentList::new(),
       component_layer: ComponentList::new(),
+      fill_window: None,
       entity_id: None,
+      cache_mode: CacheMode::Direct,
+      composite_opacity: 1.0,
+      composite_offset: (0.0, 0.0),
+      composite_scale: 1.0,
+      cache: RefCell::new(LayerCache::new()),
+      dirty: Cell::new(true),
+      anim_events: Vec::new(),
+      parents: RefCell::new(None),
+    }
+  }
+
+  /// Returns the container entity whose layout contains the given entity, if
+  /// there is one. Parents are looked up in a map built when the layer was
+  /// last laid out, so call invalidate_parents after changing containers
+  /// directly if the hierarchy is needed before the next layout.
+  pub fn get_parent(&self, e_id: EntityID) -> Option<EntityID> {
+    if self.parents.borrow().is_none() { self.update_parents(); }
+    let parents = self.parents.borrow();
+    let parents = parents.as_ref().unwrap();
+    parents.binary_search_by_key(&e_id.0, |p| p.0).ok().map(|ii| parents[ii].1)
+  }
+
+  /// Rebuilds the map get_parent looks entities' parents up in from the
+  /// layer's containers. If an entity is in more than one container, the
+  /// first container is its parent.
+  pub fn update_parents(&self) {
+    let mut parents = Vec::new();
+    for c in &self.component_container {
+      for child in c.layout.get_children() {
+        parents.push((child.0, c.entity_id));
+      }
+    }
+    // Stable sort, so dedup keeps the first container
+    parents.sort_by_key(|p| p.0);
+    parents.dedup_by_key(|p| p.0);
+    *self.parents.borrow_mut() = Some(parents);
+  }
+
+  /// Throws away the map of entities' parents, so it's rebuilt next time it's
+  /// used.
+  pub fn invalidate_parents(&self) {
+    *self.parents.borrow_mut() = None;
+  }
+
+  /// Returns the transform an entity is drawn with, combining its own
+  /// ComponentTransform with those of its container parents, then with the
+  /// transform inherited from the layer this layer is nested in.
+  pub fn get_entity_transform(&self, e_id: EntityID, inherited: &Affine2) -> Affine2 {
+    let parent_transform = self.get_parent_transform(e_id, inherited);
+    let transform = self.component_transform.get_component(e_id);
+    let aabb = self.component_aabb.get_component(e_id);
+    if transform.is_none() || aabb.is_none() { return parent_transform; }
+    transform.unwrap().get_matrix(aabb.unwrap()).then(&parent_transform)
+  }
+
+  /// Returns the transform inherited by an entity, i.e the transform of its
+  /// parents, not including its own ComponentTransform. An entity's AABB is
+  /// in the space this transform maps from.
+  pub fn get_parent_transform(&self, e_id: EntityID, inherited: &Affine2) -> Affine2 {
+    if self.component_transform.len() == 0 { return *inherited; }
+    let mut transform = Affine2::identity();
+    for id in self.get_ancestors(e_id) {
+      let t = self.component_transform.get_component(id);
+      let aabb = self.component_aabb.get_component(id);
+      if t.is_some() && aabb.is_some() {
+        transform = transform.then(&t.unwrap().get_matrix(aabb.unwrap()));
+      }
+    }
+    transform.then(inherited)
+  }
+
+  /// Returns the opacity an entity is drawn with: its own ComponentOpacity
+  /// multiplied by those of its container parents, and by the opacity
+  /// inherited from the layer this layer is nested in.
+  pub fn get_entity_opacity(&self, e_id: EntityID, inherited: f32) -> f32 {
+    if self.component_opacity.len() == 0 { return inherited; }
+    let mut opacity = inherited;
+    let o = self.component_opacity.get_component(e_id);
+    if o.is_some() { opacity *= o.unwrap().opacity; }
+    for id in self.get_ancestors(e_id) {
+      let o = self.component_opacity.get_component(id);
+      if o.is_some() { opacity *= o.unwrap().opacity; }
+    }
+    return opacity;
+  }
+
+  /// Returns false if an entity should be ignored by input, because it or one
+  /// of its container parents is fully transparent and has a ComponentOpacity
+  /// with hide_from_input set.
+  pub fn is_hittable(&self, e_id: EntityID, inherited_opacity: f32) -> bool {
+    if self.component_opacity.len() == 0 { return true; }
+    let mut ids = vec![e_id];
+    ids.extend(self.get_ancestors(e_id));
+    for id in ids {
+      let o = self.component_opacity.get_component(id);
+      if o.is_some() && o.unwrap().hide_from_input &&
+        self.get_entity_opacity(id, inherited_opacity) <= 0.0 {
+        return false;
+      }
+    }
+    return true;
+  }
+
+  /// Returns the container parents of an entity, nearest first.
+  pub fn get_ancestors(&self, e_id: EntityID) -> Vec<EntityID> {
+    let mut ancestors = Vec::new();
+    let mut curr = self.get_parent(e_id);
+    // Containers can't be nested deeper than there are containers, so this
+    // also guards against malformed (circular) hierarchies.
+    while curr.is_some() && ancestors.len() <= self.component_container.len() {
+      let id = curr.unwrap();
+      ancestors.push(id);
+      curr = self.get_parent(id);
+    }
+    return ancestors;
+  }
+
+  /// Marks this layer as changed, so that it is redrawn if it (or a layer it
+  /// is nested in) is cached. The engine does this itself when animating,
+  /// scrolling or laying out entities, but changes made to components by the
+  /// app must be followed by a call to this.
+  pub fn mark_dirty(&self) {
+    self.dirty.set(true);
+  }
+
+  /// Returns true if this layer, or any of its nested layers, has changed
+  /// since it was last drawn into its cache.
+  pub fn is_dirty(&self) -> bool {
+    if self.dirty.get() { return true; }
+    for l in &self.component_layer {
+      if l.is_dirty() { return true; }
+    }
+    return false;
+  }
+
+  /// Marks this layer and all of its nested layers as up to date. Called by
+  /// the renderer once the layer has been drawn into its cache.
+  pub fn clear_dirty(&self) {
+    self.dirty.set(false);
+    for l in &self.component_layer {
+      l.clear_dirty();
+    }
+  }
+
+  /// Returns the layer's offscreen texture cache. Used by the renderer.
+  pub fn get_cache(&self) -> &RefCell<LayerCache> {
+    &self.cache
+  }
+
+  /// Returns the clip rect of one of this layer's nested layers, i.e the AABB
+  /// of the nested layer's entity. Returns None if the nested layer has no
+  /// entity, or the entity has no AABB in this layer.
+  pub fn get_nested_clip_rect(&self, nested: &Layer) -> Option<Rect> {
+    if nested.entity_id.is_none() { return None; }
+    let aabb = self.component_aabb.get_component(nested.entity_id.unwrap());
+    if aabb.is_none() { return None; }
+    let aabb = aabb.unwrap();
+    Some(Rect::new(aabb.x, aabb.y, aabb.w, aabb.h))
+  }
+
+  /// Returns the area of an entity its children are laid out in: its AABB
+  /// minus its ComponentPadding, or minus its nine-patch's padding if it has
+  /// no ComponentPadding. None if the entity has no AABB.
+  pub fn get_content_rect(&self, e_id: EntityID) -> Option<Rect> {
+    let aabb = self.component_aabb.get_component(e_id);
+    if aabb.is_none() { return None; }
+    let aabb = aabb.unwrap();
+    let rect = Rect::new(aabb.x, aabb.y, aabb.w, aabb.h);
+    let padding = self.component_padding.get_component(e_id);
+    if padding.is_some() {
+      return Some(padding.unwrap().padding.inset(&rect));
+    }
+    let nine_patch = self.component_nine_patch.get_component(e_id);
+    if nine_patch.is_some() {
+      return Some(nine_patch.unwrap().nine_patch.get_padding().inset(&rect));
+    }
+    return Some(rect);
+  }
+
+  /// Starts animating a property of an entity, alongside any animations of
+  /// its other properties. Replaces a running animation of the same property,
+  /// reporting it as cancelled.
+  pub fn animate_property(&mut self, e_id: EntityID, anim: PropertyAnim) {
+    if self.component_anim_property.get_component(e_id).is_none() {
+      self.component_anim_property.add_component(ComponentAnimProperty::new(e_id));
+    }
+    let replaced = self.component_anim_property.get_component_mut(e_id).unwrap().add(anim);
+    if replaced.is_some() {
+      self.push_anim_event(e_id, AnimSource::Property(replaced.unwrap().property),
+                           AnimEventKind::Cancelled);
+    }
+    // The start value is applied next frame, even if the entity is paused
+    self.mark_dirty();
+  }
+
+  /// Starts a translation animation, replacing any running on the entity and
+  /// reporting it as cancelled.
+  pub fn add_anim_translate(&mut self, anim: ComponentAnimTranslate) {
+    let e_id = anim.entity_id;
+    if self.component_anim_translate.get_component(e_id).is_some() {
+      self.push_anim_event(e_id, AnimSource::Translate, AnimEventKind::Cancelled);
+    }
+    self.component_anim_translate.add_component(anim);
+    self.mark_dirty();
+  }
+
+  /// Starts a translation animation from wherever the entity is now, to the
+  /// given end location. If the entity is already being translated, the new
+  /// animation starts at its current on-screen velocity rather than the
+  /// tween's, so animations can be retargeted mid-flight smoothly. Does
+  /// nothing if the entity has no AABB.
+  pub fn retarget_translate(&mut self, e_id: EntityID, end_x: f32, end_y: f32,
+                            anim_len: f64, tween_func: TweenFunction) {
+    let (x, y) = {
+      let aabb = self.component_aabb.get_component(e_id);
+      if aabb.is_none() { return; }
+      let aabb = aabb.unwrap();
+      (aabb.x, aabb.y)
+    };
+    let rate = self.component_anim_playback.get_component(e_id)
+      .map_or(1.0, |p| p.get_rate());
+    let mut velocity = self.component_anim_translate.get_component(e_id)
+      .map(|a| a.get_velocity(rate));
+    // The new animation plays at the same rate, so the on-screen velocity is
+    // carried on in its own time
+    if velocity.is_some() && rate != 0.0 {
+      let v = velocity.unwrap();
+      velocity = Some((v.0 / rate as f32, v.1 / rate as f32));
+    }
+    self.add_anim_translate(ComponentAnimTranslate {
+      entity_id: e_id,
+      start_x: x, start_y: y,
+      end_x: end_x, end_y: end_y,
+      anim_len: anim_len,
+      anim_timer: 0.0,
+      tween_func: tween_func,
+      start_velocity: velocity,
+    });
+  }
+
+  /// Fades an entity's opacity from start to end, adding a ComponentOpacity
+  /// if it doesn't have one. Shorthand for animate_property with
+  /// AnimProperty::Opacity, so it replaces a running fade in the same way.
+  pub fn add_anim_opacity(&mut self, e_id: EntityID, start: f32, end: f32,
+                          anim_len: f64, tween_func: TweenFunction) {
+    self.animate_property(e_id, PropertyAnim::new(
+      AnimProperty::Opacity { start: start, end: end }, anim_len, tween_func));
+  }
+
+  /// Starts a timeline, replacing any running on the entity and reporting it
+  /// as cancelled.
+  pub fn add_anim_timeline(&mut self, anim: ComponentAnimTimeline) {
+    let e_id = anim.entity_id;
+    if self.component_anim_timeline.get_component(e_id).is_some() {
+      self.push_anim_event(e_id, AnimSource::Timeline, AnimEventKind::Cancelled);
+    }
+    self.component_anim_timeline.add_component(anim);
+    self.mark_dirty();
+  }
+
+  /// Starts a decay animation, replacing any running on the entity and
+  /// reporting it as cancelled.
+  pub fn add_anim_decay(&mut self, anim: ComponentAnimDecay) {
+    let e_id = anim.entity_id;
+    if self.component_anim_decay.get_component(e_id).is_some() {
+      self.push_anim_event(e_id, AnimSource::Decay, AnimEventKind::Cancelled);
+    }
+    self.component_anim_decay.add_component(anim);
+  }
+
+  /// Stops an entity's translation and decay animations where they are,
+  /// reporting them as cancelled. E.g when a finger catches a flung entity.
+  pub fn cancel_motion(&mut self, e_id: EntityID) {
+    let index = self.component_anim_translate.get_component_index(e_id);
+    if index.is_some() {
+      self.component_anim_translate.remove(index.unwrap());
+      self.push_anim_event(e_id, AnimSource::Translate, AnimEventKind::Cancelled);
+    }
+    let index = self.component_anim_decay.get_component_index(e_id);
+    if index.is_some() {
+      self.component_anim_decay.remove(index.unwrap());
+      self.push_anim_event(e_id, AnimSource::Decay, AnimEventKind::Cancelled);
+    }
+  }
+
+  /// Starts animating an entity to the closest of its ComponentScrollSnap's
+  /// snap positions. Returns false if the entity has no snap positions or no
+  /// AABB.
+  pub fn start_scroll_snap(&mut self, e_id: EntityID) -> bool {
+    let snap = self.component_scroll_snap.get_component(e_id).cloned();
+    if snap.is_none() { return false; }
+    let snap = snap.unwrap();
+    let pos = {
+      let aabb = self.component_aabb.get_component(e_id);
+      if aabb.is_none() { return false; }
+      let aabb = aabb.unwrap();
+      (aabb.x, aabb.y)
+    };
+
+    // Find the closest snap point
+    let mut closest = None;
+    let mut shortest = ::std::f32::MAX;
+    for p in &snap.snap_positions {
+      let dis = vec::sq_distance(*p, pos);
+      if closest.is_none() || shortest > dis {
+        closest = Some(*p);
+        shortest = dis;
+      }
+    }
+    if closest.is_none() { return false; }
+    let closest = closest.unwrap();
+    self.retarget_translate(e_id, closest.0, closest.1, snap.tween_len, snap.tween_func);
+    return true;
+  }
+
+  /// Stops all of an entity's animations where they are, reporting each as
+  /// cancelled.
+  pub fn cancel_animations(&mut self, e_id: EntityID) {
+    let index = self.component_anim_translate.get_component_index(e_id);
+    if index.is_some() {
+      self.component_anim_translate.remove(index.unwrap());
+      self.push_anim_event(e_id, AnimSource::Translate, AnimEventKind::Cancelled);
+    }
+    let index = self.component_anim_property.get_component_index(e_id);
+    if index.is_some() {
+      let anims = self.component_anim_property[index.unwrap()].anims.clone();
+      self.component_anim_property.remove(index.unwrap());
+      for a in anims {
+        self.push_anim_event(e_id, AnimSource::Property(a.property),
+                             AnimEventKind::Cancelled);
+      }
+    }
+    let index = self.component_anim_timeline.get_component_index(e_id);
+    if index.is_some() {
+      self.component_anim_timeline.remove(index.unwrap());
+      self.push_anim_event(e_id, AnimSource::Timeline, AnimEventKind::Cancelled);
+    }
+    let index = self.component_anim_decay.get_component_index(e_id);
+    if index.is_some() {
+      self.component_anim_decay.remove(index.unwrap());
+      self.push_anim_event(e_id, AnimSource::Decay, AnimEventKind::Cancelled);
+    }
+  }
+
+  /// Returns an entity's ComponentAnimPlayback, adding one if it doesn't have
+  /// one.
+  fn get_playback_mut(&mut self, e_id: EntityID) -> &mut ComponentAnimPlayback {
+    if self.component_anim_playback.get_component(e_id).is_none() {
+      self.component_anim_playback.add_component(ComponentAnimPlayback::new(e_id));
+    }
+    self.component_anim_playback.get_component_mut(e_id).unwrap()
+  }
+
+  /// Freezes all of an entity's animations where they are.
+  pub fn pause_animations(&mut self, e_id: EntityID) {
+    self.get_playback_mut(e_id).paused = true;
+  }
+
+  /// Continues an entity's animations after pause_animations.
+  pub fn resume_animations(&mut self, e_id: EntityID) {
+    self.get_playback_mut(e_id).paused = false;
+  }
+
+  /// Sets the speed an entity's animations play at, where 1 is normal speed
+  /// and negative rates play them backwards.
+  pub fn set_animation_rate(&mut self, e_id: EntityID, rate: f64) {
+    self.get_playback_mut(e_id).rate = rate;
+  }
+
+  /// Reverses the direction an entity's animations are playing in. Animations
+  /// playing backwards finish once they are back at their start.
+  pub fn reverse_animations(&mut self, e_id: EntityID) {
+    let playback = self.get_playback_mut(e_id);
+    playback.rate = -playback.rate;
+  }
+
+  /// Moves all of an entity's animations to the given time in seconds from
+  /// their start, clamped to their lengths. The animations are applied on the
+  /// next frame, even if paused.
+  pub fn seek_animations(&mut self, e_id: EntityID, time: f64) {
+    let time = time.max(0.0);
+    {
+      let anim = self.component_anim_translate.get_component_mut(e_id);
+      if anim.is_some() {
+        let anim = anim.unwrap();
+        anim.anim_timer = time.min(anim.get_len());
+      }
+    }
+    {
+      let anim = self.component_anim_property.get_component_mut(e_id);
+      if anim.is_some() {
+        for a in &mut anim.unwrap().anims {
+          a.anim_timer = time.min(a.get_len());
+        }
+      }
+    }
+    {
+      let anim = self.component_anim_timeline.get_component_mut(e_id);
+      if anim.is_some() {
+        let anim = anim.unwrap();
+        let len = anim.animation.get_len();
+        anim.anim_timer = if len.is_some() { time.min(len.unwrap()) } else { time };
+      }
+    }
+    self.mark_dirty();
+  }
+
+  /// Seeks the animations of every entity in this layer and its nested
+  /// layers. See seek_animations.
+  pub fn seek_all_animations(&mut self, time: f64) {
+    let mut ids : Vec<EntityID> = Vec::new();
+    ids.extend(self.component_anim_translate.into_iter().map(|a| a.entity_id));
+    ids.extend(self.component_anim_property.into_iter().map(|a| a.entity_id));
+    ids.extend(self.component_anim_timeline.into_iter().map(|a| a.entity_id));
+    for id in ids {
+      self.seek_animations(id, time);
+    }
+    for l in &mut self.component_layer {
+      l.seek_all_animations(time);
+    }
+  }
+
+  /// Queues an animation event, to be sent to the app's AnimListeners after
+  /// the next frame. Used by the animation system.
+  pub fn push_anim_event(&mut self, e_id: EntityID, source: AnimSource,
+                         kind: AnimEventKind) {
+    self.anim_events.push(AnimEvent {
+      entity_id: e_id,
+      source: source,
+      kind: kind,
+    });
+  }
+
+  /// Moves the queued animation events of this layer and its nested layers
+  /// into out.
+  pub fn drain_anim_events(&mut self, out: &mut Vec<AnimEvent>) {
+    out.extend(self.anim_events.drain(..));
+    for l in &mut self.component_layer {
+      l.drain_anim_events(out);
+    }
+  }
+
+  /// Returns the state a nested layer inherits: the transform and opacity of
+  /// the entity it belongs to.
+  pub fn get_nested_inherited(&self, nested: &Layer, inherited: &Inherited) -> Inherited {
+    if nested.entity_id.is_none() { return *inherited; }
+    let e_id = nested.entity_id.unwrap();
+    Inherited {
+      transform: self.get_entity_transform(e_id, &inherited.transform),
+      opacity: self.get_entity_opacity(e_id, inherited.opacity),
     }
   }
 }
@@ -63,6 +608,13 @@ impl Layer {
 pub struct View<'a> {
   pub view_listeners : Vec<&'a ViewListener>,
   pub layers : Vec<Layer>,
+  /// Generation of the theme last applied to this view, or None if no theme
+  /// has been applied yet. See LibState::set_theme.
+  theme_generation : Option<u64>,
+  /// True if all of the view's animations are paused
+  anim_paused : bool,
+  /// Speed all of the view's animations play at, see set_animation_rate
+  anim_rate : f64,
 }
 
 impl<'a> View<'a> {
@@ -70,9 +622,20 @@ impl<'a> View<'a> {
     View {
       view_listeners : Vec::new(),
       layers: Vec::new(),
+      theme_generation: None,
+      anim_paused: false,
+      anim_rate: 1.0,
     }
   }
 
+  pub fn get_theme_generation(&self) -> Option<u64> {
+    self.theme_generation
+  }
+
+  pub fn set_theme_generation(&mut self, generation: u64) {
+    self.theme_generation = Some(generation);
+  }
+
   /// Run the layout manager on this view. Alters AABB components based on
   /// Container component hierarchies. If the hierarchy is malforms (component
   /// with multiple parents / circular hierarchy) then this thread will panic.
@@ -81,4 +644,74 @@ impl<'a> View<'a> {
       manager::layout_layer(layer);
     }
   }
+
+  /// Freezes all of the view's animations where they are.
+  pub fn pause_animations(&mut self) {
+    self.anim_paused = true;
+  }
+
+  /// Continues the view's animations after pause_animations.
+  pub fn resume_animations(&mut self) {
+    self.anim_paused = false;
+  }
+
+  pub fn is_animation_paused(&self) -> bool {
+    self.anim_paused
+  }
+
+  /// Sets the speed all of the view's animations play at, on top of each
+  /// entity's own rate. 1 is normal speed, negative rates play backwards.
+  pub fn set_animation_rate(&mut self, rate: f64) {
+    self.anim_rate = rate;
+  }
+
+  pub fn get_animation_rate(&self) -> f64 {
+    self.anim_rate
+  }
+
+  /// Reverses the direction all of the view's animations are playing in.
+  pub fn reverse_animations(&mut self) {
+    self.anim_rate = -self.anim_rate;
+  }
+
+  /// Moves every animation in the view to the given time in seconds from
+  /// its start. See Layer::seek_animations.
+  pub fn seek_animations(&mut self, time: f64) {
+    for layer in &mut self.layers {
+      layer.seek_all_animations(time);
+    }
+  }
+
+  /// Moves the view's animations on by the given number of seconds and
+  /// applies them, as a frame does. The run loop steps the top view itself,
+  /// so this is for driving animations without a window, e.g in tests. The
+  /// app's time scale isn't applied, but playback controls and the view's
+  /// rate are.
+  pub fn step_animations(&mut self, delta: f64) {
+    animation::process_view_animations(self, delta);
+  }
+
+  /// Moves the queued animation events of every layer in this view into out.
+  pub fn drain_anim_events(&mut self, out: &mut Vec<AnimEvent>) {
+    for layer in &mut self.layers {
+      layer.drain_anim_events(out);
+    }
+  }
+
+  /// Sets the AABB of each root layer's fill_window entity (if it has one) to
+  /// cover a window of the given size.
+  pub fn fill_window(&mut self, w: f32, h: f32) {
+    for layer in &mut self.layers {
+      if layer.fill_window.is_none() { continue; }
+      let aabb = layer.component_aabb.get_component_mut(layer.fill_window.unwrap());
+      if aabb.is_none() { continue; }
+      let aabb = aabb.unwrap();
+      if aabb.x == 0.0 && aabb.y == 0.0 && aabb.w == w && aabb.h == h { continue; }
+      aabb.x = 0.0;
+      aabb.y = 0.0;
+      aabb.w = w;
+      aabb.h = h;
+      layer.mark_dirty();
+    }
+  }
 }